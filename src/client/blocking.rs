@@ -0,0 +1,141 @@
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+
+use super::{decode, mempool_path, sealed_in, transfer_req, ClientBuilder, ClientError, Request};
+use crate::database::{Account, Amount, SigningKey, Tx};
+use crate::node::{
+    AccountRes, BalanceRes, BlockHeaderRes, BlockRes, MempoolDropRes, MempoolQuery, MempoolRes,
+    MempoolTxRes, StatusRes, TxAddReq, TxAddRes,
+};
+
+/// Blocking client for the node HTTP API. Must not be used from inside an
+/// async runtime, use [`super::Client`] there.
+#[derive(Debug, Clone)]
+pub struct BlockingClient {
+    http: reqwest::blocking::Client,
+    config: ClientBuilder,
+}
+
+impl ClientBuilder {
+    pub fn build_blocking(self) -> Result<BlockingClient, ClientError> {
+        let http = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .build()?;
+        Ok(BlockingClient { http, config: self })
+    }
+}
+
+impl BlockingClient {
+    pub fn status(&self) -> Result<StatusRes, ClientError> {
+        self.send(Request::get("/node/status".to_string()))
+    }
+
+    pub fn balances(&self) -> Result<BalanceRes, ClientError> {
+        self.send(Request::get("/balances/list".to_string()))
+    }
+
+    pub fn account(&self, account: &str) -> Result<AccountRes, ClientError> {
+        self.send(Request::get(format!("/accounts/{}", account)))
+    }
+
+    pub fn add_tx(&self, req: &TxAddReq) -> Result<TxAddRes, ClientError> {
+        self.send(Request::add_tx(req)?)
+    }
+
+    /// Sends `value` from the key's account to `to` at the minimum fee, using
+    /// the sender's next pending nonce.
+    pub fn transfer(
+        &self,
+        key: &SigningKey,
+        to: &Account,
+        value: Amount,
+    ) -> Result<TxAddRes, ClientError> {
+        let status = self.status()?;
+        let account = self.account(key.account())?;
+        self.add_tx(&transfer_req(key, to, value, &status, &account)?)
+    }
+
+    pub fn mempool(&self, query: &MempoolQuery) -> Result<MempoolRes, ClientError> {
+        self.send(Request::get(mempool_path(query)))
+    }
+
+    pub fn mempool_tx(&self, tx_hash: &str) -> Result<MempoolTxRes, ClientError> {
+        self.send(Request::get(format!("/mempool/{}", tx_hash)))
+    }
+
+    pub fn drop_mempool_tx(&self, tx_hash: &str) -> Result<MempoolDropRes, ClientError> {
+        self.send(Request::delete_admin(format!("/mempool/{}", tx_hash)))
+    }
+
+    pub fn block(&self, number: u64) -> Result<BlockRes, ClientError> {
+        self.send(Request::get(format!("/blocks/{}", number)))
+    }
+
+    pub fn block_header(&self, number: u64) -> Result<BlockHeaderRes, ClientError> {
+        self.send(Request::get(format!("/blocks/{}/header", number)))
+    }
+
+    /// Whether `tx` is on chain, looking back from the latest block until the
+    /// sender's nonce rules it out. Runs without retries of its own.
+    fn is_sealed(&self, tx: &Tx) -> Result<bool, ClientError> {
+        let account: AccountRes =
+            self.send_once(&Request::get(format!("/accounts/{}", tx.from())))?;
+        if account.nonce <= tx.nonce() {
+            return Ok(false);
+        }
+        let status: StatusRes = self.send_once(&Request::get("/node/status".to_string()))?;
+        let mut number = status.number;
+        loop {
+            let res: BlockRes = self.send_once(&Request::get(format!("/blocks/{}", number)))?;
+            if let Some(sealed) = sealed_in(&res.block, tx)? {
+                return Ok(sealed);
+            }
+            let Some(parent) = number.checked_sub(1) else {
+                return Ok(false);
+            };
+            number = parent;
+        }
+    }
+
+    fn send<T: serde::de::DeserializeOwned>(&self, req: Request) -> Result<T, ClientError> {
+        let mut attempt = 0;
+        loop {
+            let err = match self.send_once(&req) {
+                Ok(res) => return Ok(res),
+                Err(err) => err,
+            };
+            if let Some(body) = req.duplicate_body(&err, attempt) {
+                return decode(reqwest::StatusCode::OK, &body);
+            }
+            if let Some((tx, body)) = req.nonce_used(&err, attempt) {
+                if let Ok(true) = self.is_sealed(tx) {
+                    return decode(reqwest::StatusCode::OK, body);
+                }
+            }
+            if !req.should_retry(&err, attempt, self.config.retries) {
+                return Err(err);
+            }
+            std::thread::sleep(self.config.delay(attempt));
+            attempt += 1;
+        }
+    }
+
+    fn send_once<T: serde::de::DeserializeOwned>(&self, req: &Request) -> Result<T, ClientError> {
+        let mut builder = self.http.request(
+            req.method.clone(),
+            format!("{}{}", self.config.url, req.path),
+        );
+        if let Some(body) = &req.body {
+            builder = builder
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+        }
+        if let (true, Some(token)) = (req.admin, &self.config.admin_token) {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let res = builder.send()?;
+        let status = res.status();
+        let body = res.bytes()?;
+        decode(status, &body)
+    }
+}
@@ -0,0 +1,504 @@
+mod blocking;
+#[allow(clippy::module_inception)]
+mod client;
+
+use std::time::Duration;
+
+use data_encoding::HEXLOWER;
+use reqwest::{Method, StatusCode};
+
+use crate::database::{Account, Amount, Block, DatabaseError, SigningKey, Tx};
+use crate::node::{AccountRes, ErrorBody, ErrorRes, MempoolQuery, StatusRes, TxAddReq, TxAddRes};
+
+pub use blocking::*;
+pub use client::*;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or the response could not be read.
+    Http(reqwest::Error),
+    /// The node answered with an error status.
+    Api { status: u16, error: ErrorBody },
+    /// The node answered with a body that is not the expected type.
+    Decode(String),
+    /// The transaction could not be hashed or signed locally.
+    Tx(DatabaseError),
+}
+
+impl ClientError {
+    /// The node's error code, e.g. `insufficient_funds` or `pruned`.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ClientError::Api { error, .. } => Some(&error.code),
+            _ => None,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Http(err) => err.is_connect() || err.is_timeout(),
+            ClientError::Api { status, .. } => matches!(
+                StatusCode::from_u16(*status),
+                Ok(StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT)
+            ),
+            ClientError::Decode(_) | ClientError::Tx(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "error contacting node: {}", err),
+            ClientError::Api { status, error } => write!(
+                f,
+                "node returned {}: {} ({})",
+                status, error.message, error.code
+            ),
+            ClientError::Decode(err) => write!(f, "error reading node response: {}", err),
+            ClientError::Tx(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(err) => Some(err),
+            ClientError::Tx(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        ClientError::Http(err)
+    }
+}
+
+impl From<DatabaseError> for ClientError {
+    fn from(err: DatabaseError) -> Self {
+        ClientError::Tx(err)
+    }
+}
+
+/// Configures a [`Client`] or [`BlockingClient`] for the node at a base URL
+/// such as `http://127.0.0.1:8080`.
+///
+/// Requests that fail to connect, time out, or get a 429, 502, 503 or 504 are
+/// retried with exponential backoff. `POST /tx/add` is only retried when the
+/// request pins nonce, fee and chain id. A `duplicate` answer to such a retry
+/// counts as success, and so does an `invalid_nonce` answer once the tx is
+/// found on chain.
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    url: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    admin_token: Option<String>,
+}
+
+impl ClientBuilder {
+    pub fn new(url: &str) -> ClientBuilder {
+        ClientBuilder {
+            url: url.trim_end_matches('/').to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            admin_token: None,
+        }
+    }
+
+    /// Limit for each attempt from connecting to reading the body, 10s by
+    /// default.
+    pub fn timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limit for establishing the connection, 5s by default.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> ClientBuilder {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Attempts after the first one, 3 by default. 0 disables retries.
+    pub fn retries(mut self, retries: u32) -> ClientBuilder {
+        self.retries = retries;
+        self
+    }
+
+    /// Wait before the first retry, doubled for each further one. 250ms by
+    /// default.
+    pub fn backoff(mut self, backoff: Duration) -> ClientBuilder {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Token sent to admin endpoints such as `DELETE /mempool/{tx_hash}`.
+    pub fn admin_token(mut self, admin_token: &str) -> ClientBuilder {
+        self.admin_token = Some(admin_token.to_string());
+        self
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+#[derive(Debug)]
+struct Request {
+    method: Method,
+    path: String,
+    body: Option<Vec<u8>>,
+    admin: bool,
+    retry: bool,
+    pinned: Option<(Tx, Vec<u8>)>,
+}
+
+impl Request {
+    fn get(path: String) -> Request {
+        Request {
+            method: Method::GET,
+            path,
+            body: None,
+            admin: false,
+            retry: true,
+            pinned: None,
+        }
+    }
+
+    fn delete_admin(path: String) -> Request {
+        Request {
+            method: Method::DELETE,
+            admin: true,
+            ..Request::get(path)
+        }
+    }
+
+    fn add_tx(req: &TxAddReq) -> Result<Request, ClientError> {
+        let body = encode(req)?;
+        let pinned = match req.tx() {
+            Some(tx) => {
+                let res = encode(&TxAddRes {
+                    hash: HEXLOWER.encode(&tx.hash()?),
+                })?;
+                Some((tx, res))
+            }
+            None => None,
+        };
+        Ok(Request {
+            method: Method::POST,
+            path: "/tx/add".to_string(),
+            body: Some(body),
+            admin: false,
+            retry: pinned.is_some(),
+            pinned,
+        })
+    }
+
+    fn should_retry(&self, err: &ClientError, attempt: u32, retries: u32) -> bool {
+        self.retry && attempt < retries && err.is_retryable()
+    }
+
+    /// The body to return instead of `err` when a retried tx was already
+    /// admitted by an earlier attempt whose response got lost.
+    fn duplicate_body(&self, err: &ClientError, attempt: u32) -> Option<Vec<u8>> {
+        match err.code() {
+            Some("duplicate") if attempt > 0 => self.pinned.as_ref().map(|(_, res)| res.clone()),
+            _ => None,
+        }
+    }
+
+    /// The tx and the body to return instead of `err` if an earlier attempt
+    /// already got the retried tx sealed, which leaves its nonce used.
+    fn nonce_used(&self, err: &ClientError, attempt: u32) -> Option<&(Tx, Vec<u8>)> {
+        match err.code() {
+            Some("invalid_nonce") if attempt > 0 => self.pinned.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `block` settles if `tx` is on chain: `Some(true)` if it holds `tx`,
+/// `Some(false)` if it holds another tx with the same nonce or an earlier one
+/// from the sender, `None` if the search has to go on to older blocks.
+fn sealed_in(block: &Block, tx: &Tx) -> Result<Option<bool>, ClientError> {
+    for sealed in block.txs().iter().rev() {
+        if sealed.from() != tx.from() || sealed.nonce() > tx.nonce() {
+            continue;
+        }
+        if sealed.nonce() < tx.nonce() {
+            return Ok(Some(false));
+        }
+        return Ok(Some(sealed.hash()? == tx.hash()?));
+    }
+    Ok(None)
+}
+
+fn mempool_path(query: &MempoolQuery) -> String {
+    let mut params = Vec::new();
+    if let Some(offset) = query.offset {
+        params.push(format!("offset={}", offset));
+    }
+    if let Some(limit) = query.limit {
+        params.push(format!("limit={}", limit));
+    }
+    if params.is_empty() {
+        "/mempool".to_string()
+    } else {
+        format!("/mempool?{}", params.join("&"))
+    }
+}
+
+fn transfer_req(
+    key: &SigningKey,
+    to: &Account,
+    value: Amount,
+    status: &StatusRes,
+    account: &AccountRes,
+) -> Result<TxAddReq, ClientError> {
+    let tx = Tx::new(
+        &status.chain_id,
+        key.account().clone(),
+        to.clone(),
+        value,
+        status.min_fee,
+        account.pending_nonce,
+        "",
+    );
+    Ok(TxAddReq::signed(&tx, key)?)
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, ClientError> {
+    serde_json::to_vec(value).map_err(|err| ClientError::Decode(err.to_string()))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(
+    status: StatusCode,
+    body: &[u8],
+) -> Result<T, ClientError> {
+    if status.is_success() {
+        return serde_json::from_slice(body).map_err(|err| ClientError::Decode(err.to_string()));
+    }
+    let error = match serde_json::from_slice::<ErrorRes>(body) {
+        Ok(res) => res.error,
+        Err(_) => ErrorBody {
+            code: "unexpected_response".to_string(),
+            message: String::from_utf8_lossy(body).into_owned(),
+        },
+    };
+    Err(ClientError::Api {
+        status: status.as_u16(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+
+    use super::*;
+    use crate::database::{dev_accounts, DevAccount};
+    use crate::node::{NodeBuilder, RunningNode};
+
+    fn dev_node(accounts: usize) -> (RunningNode, Vec<DevAccount>) {
+        let node = NodeBuilder::dev(accounts)
+            .unwrap()
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .spawn()
+            .unwrap();
+        (node, dev_accounts(accounts).unwrap())
+    }
+
+    #[test]
+    fn blocking_client_signs_and_submits_transfers() {
+        let (node, accounts) = dev_node(2);
+        let client = ClientBuilder::new(&node.url()).build_blocking().unwrap();
+        let key = accounts[0].signing_key().unwrap();
+
+        let status = client.status().unwrap();
+        assert_eq!(status.chain_id, "tbb-dev");
+        let res = client
+            .transfer(&key, &accounts[1].account, Amount::new(7))
+            .unwrap();
+        let block = client.block(0).unwrap().block;
+        let sealed = block.txs().last().unwrap();
+        assert_eq!(res.hash, HEXLOWER.encode(&sealed.hash().unwrap()));
+        assert_eq!(
+            client.mempool(&MempoolQuery::default()).unwrap().total_txs,
+            0
+        );
+
+        let receiver = client.account(&accounts[1].account).unwrap();
+        assert_eq!(receiver.balance, Amount::new(1_000_007));
+        let sender = client.account(&accounts[0].account).unwrap();
+        assert_eq!(sender.nonce, sealed.nonce() + 1);
+        assert_eq!(sender.pending_nonce, sender.nonce);
+
+        let tx = Tx::new(
+            &status.chain_id,
+            accounts[1].account.clone(),
+            accounts[0].account.clone(),
+            Amount::new(1),
+            status.min_fee,
+            0,
+            "",
+        );
+        let forged = TxAddReq::signed(&tx, &key);
+        assert!(matches!(forged, Err(DatabaseError::BadSignature(_))));
+        let mut forged = TxAddReq::signed(&tx, &accounts[1].signing_key().unwrap()).unwrap();
+        forged.value = Amount::new(2);
+        let err = client.add_tx(&forged).unwrap_err();
+        assert_eq!(err.code(), Some("bad_signature"));
+        assert_eq!(client.balances().unwrap().balances.len(), 2);
+
+        drop(client);
+        node.stop().unwrap();
+    }
+
+    /// Forwards requests to `upstream` but answers the first `POST /tx/add`
+    /// with a 503 after the node handled it, as if the response got lost.
+    fn lossy_proxy(upstream: String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let http = reqwest::blocking::Client::builder()
+                .pool_max_idle_per_host(0)
+                .build()
+                .unwrap();
+            let mut lost = false;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let mut parts = request_line.split_whitespace();
+                let method: Method = parts.next().unwrap().parse().unwrap();
+                let path = parts.next().unwrap();
+                let res = http
+                    .request(method, format!("{}{}", upstream, path))
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send()
+                    .unwrap();
+                let mut status = res.status().as_u16();
+                let mut body = res.bytes().unwrap().to_vec();
+                if path == "/tx/add" && !lost {
+                    lost = true;
+                    status = 503;
+                    body = b"lost".to_vec();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Proxied\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn retry_of_a_sealed_tx_counts_as_added() {
+        let (node, accounts) = dev_node(2);
+        let proxy = lossy_proxy(node.url());
+        let client = ClientBuilder::new(&format!("http://{}", proxy))
+            .backoff(Duration::from_millis(10))
+            .build_blocking()
+            .unwrap();
+        let key = accounts[0].signing_key().unwrap();
+
+        let res = client
+            .transfer(&key, &accounts[1].account, Amount::new(7))
+            .unwrap();
+        let block = client.block(0).unwrap().block;
+        let sealed = block.txs().last().unwrap();
+        assert_eq!(res.hash, HEXLOWER.encode(&sealed.hash().unwrap()));
+        assert!(client.block(1).is_err());
+        let receiver = client.account(&accounts[1].account).unwrap();
+        assert_eq!(receiver.balance, Amount::new(1_000_007));
+
+        drop(client);
+        node.stop().unwrap();
+    }
+
+    #[test]
+    fn async_client_runs_on_the_actix_runtime() {
+        let (node, accounts) = dev_node(2);
+        let key = accounts[0].signing_key().unwrap();
+        let url = node.url();
+        let balance = actix_web::rt::System::new().block_on(async move {
+            let client = ClientBuilder::new(&url).build().unwrap();
+            client
+                .transfer(&key, &accounts[1].account, Amount::new(5))
+                .await
+                .unwrap();
+            let header = client.block_header(0).await.unwrap();
+            assert_eq!(header.header.number(), 0);
+            client.account(&accounts[1].account).await.unwrap().balance
+        });
+        assert_eq!(balance, Amount::new(1_000_005));
+        node.stop().unwrap();
+    }
+
+    #[test]
+    fn retries_until_the_node_is_up() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let builder = NodeBuilder::dev(1).unwrap().bind(addr);
+        let starter = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            builder.spawn().unwrap()
+        });
+
+        let client = ClientBuilder::new(&format!("http://{}", addr))
+            .retries(0)
+            .build_blocking()
+            .unwrap();
+        let err = client.status().unwrap_err();
+        assert!(matches!(err, ClientError::Http(_)), "{}", err);
+
+        let client = ClientBuilder::new(&format!("http://{}", addr))
+            .retries(8)
+            .backoff(Duration::from_millis(50))
+            .build_blocking()
+            .unwrap();
+        assert_eq!(client.status().unwrap().chain_id, "tbb-dev");
+
+        drop(client);
+        starter.join().unwrap().stop().unwrap();
+    }
+}
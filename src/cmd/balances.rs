@@ -1,13 +1,15 @@
-use crate::database::State;
+use tbb::database::State;
 
 const FLAG_DATA_DIR: &str = "datadir";
 
 pub fn balances_cmd() -> clap::Command {
     let list_cmd = balances_list_cmd();
+    let supply_cmd = balances_supply_cmd();
 
     clap::Command::new("balances")
         .about("Interact with balances")
         .subcommand(list_cmd)
+        .subcommand(supply_cmd)
 }
 
 fn balances_list_cmd() -> clap::Command {
@@ -20,10 +22,27 @@ fn balances_list_cmd() -> clap::Command {
     )
 }
 
+fn balances_supply_cmd() -> clap::Command {
+    clap::Command::new("supply")
+        .about("Show the circulating supply and the reward schedule")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+}
+
 pub fn get_database_state_from_disk(args: &clap::ArgMatches) {
     let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
-    let state = State::new_state_from_disk(datadir);
-    state.close();
+    let state = match State::new_read_only_state_from_disk(datadir) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("Error loading state: {}", err);
+            std::process::exit(1);
+        }
+    };
     println!("Accounts balances at: {:?}", state.latest_block_hash());
     println!("__________________");
     println!();
@@ -31,3 +50,35 @@ pub fn get_database_state_from_disk(args: &clap::ArgMatches) {
         println!("{}: {}", account, balance);
     }
 }
+
+pub fn print_supply_from_disk(args: &clap::ArgMatches) {
+    let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let state = match State::new_read_only_state_from_disk(datadir) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("Error loading state: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let supply = state.supply();
+    let consensus = state.consensus();
+    let next_block_number = state.next_block_number();
+    match state.circulating_supply() {
+        Some(total) => println!("Circulating supply: {} TBB", total),
+        None => println!("Circulating supply: overflow"),
+    }
+    println!(
+        "Genesis {} TBB + minted {} TBB - burned {} TBB",
+        supply.genesis, supply.minted, supply.burned
+    );
+    println!("Max supply: {} TBB", consensus.rewards.max_supply);
+    println!(
+        "Reward for block {}: {} TBB",
+        next_block_number,
+        state.block_reward(next_block_number)
+    );
+    match state.next_halving_height(next_block_number) {
+        Some(height) => println!("Next halving at block {}", height),
+        None => println!("No further halvings"),
+    }
+}
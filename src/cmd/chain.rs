@@ -0,0 +1,280 @@
+use data_encoding::HEXLOWER;
+
+use tbb::database::{
+    export_chain, import_chain, prune_data_dir, repair_chain, verify_chain, ChainFormat,
+};
+
+const FLAG_DATA_DIR: &str = "datadir";
+const FLAG_FROM: &str = "from";
+const FLAG_TO: &str = "to";
+const FLAG_FORMAT: &str = "format";
+const FLAG_OUT: &str = "out";
+const FLAG_FILE: &str = "file";
+const FLAG_KEEP: &str = "keep";
+const IMPORT_PROGRESS_EVERY: u64 = 1000;
+
+pub fn chain_cmd() -> clap::Command {
+    let verify_cmd = chain_verify_cmd();
+    let repair_cmd = chain_repair_cmd();
+    let export_cmd = chain_export_cmd();
+    let import_cmd = chain_import_cmd();
+    let prune_cmd = chain_prune_cmd();
+
+    clap::Command::new("chain")
+        .about("Inspect the blockchain")
+        .subcommand(verify_cmd)
+        .subcommand(repair_cmd)
+        .subcommand(export_cmd)
+        .subcommand(import_cmd)
+        .subcommand(prune_cmd)
+}
+
+fn chain_verify_cmd() -> clap::Command {
+    clap::Command::new("verify")
+        .about("Verify every block stored in the data directory")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+}
+
+fn chain_repair_cmd() -> clap::Command {
+    clap::Command::new("repair")
+        .about("Truncate the blockchain at the first inconsistent block")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+}
+
+fn chain_export_cmd() -> clap::Command {
+    clap::Command::new("export")
+        .about("Write a range of blocks to a file")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_FROM)
+                .long("from")
+                .help("First block to export")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_TO)
+                .long("to")
+                .help("Last block to export, defaults to the latest block")
+                .value_parser(clap::value_parser!(u64))
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_FORMAT)
+                .long("format")
+                .help("jsonl or binary")
+                .default_value("jsonl")
+                .value_parser(clap::value_parser!(ChainFormat))
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_OUT)
+                .long("out")
+                .help("File to write the blocks to")
+                .required(true)
+                .num_args(1),
+        )
+}
+
+fn chain_import_cmd() -> clap::Command {
+    clap::Command::new("import")
+        .about("Validate and append the blocks of an exported file")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_FILE)
+                .help("File written by `tbb chain export`, in jsonl or binary format")
+                .required(true)
+                .num_args(1),
+        )
+}
+
+fn chain_prune_cmd() -> clap::Command {
+    clap::Command::new("prune")
+        .about("Drop block bodies older than the most recent blocks, keeping their headers")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_KEEP)
+                .long("keep")
+                .help("Number of recent blocks to keep bodies for, defaults to pruning.keep_blocks of config.json")
+                .value_parser(clap::value_parser!(u64))
+                .num_args(1),
+        )
+}
+
+pub fn verify_chain_on_disk(args: &clap::ArgMatches) {
+    let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let report = match verify_chain(datadir) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Chain verification failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if let Some(err) = report.error {
+        eprintln!("Chain verification failed at {}", err);
+        std::process::exit(1);
+    }
+    println!("Chain is valid");
+    println!("__________________");
+    println!();
+    println!("Blocks: {}", report.blocks);
+    if let Some(anchor) = report.anchor {
+        println!("Starts after snapshot block: {}", anchor);
+    }
+    println!("Genesis hash: {}", HEXLOWER.encode(&report.genesis_hash));
+    println!(
+        "Latest block hash: {}",
+        HEXLOWER.encode(&report.latest_block_hash)
+    );
+    if let Some(total) = report.supply.total() {
+        println!(
+            "Total supply: {} TBB (genesis {} + minted {} - burned {})",
+            total, report.supply.genesis, report.supply.minted, report.supply.burned
+        );
+    }
+}
+
+pub fn repair_chain_on_disk(args: &clap::ArgMatches) {
+    let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let report = match repair_chain(datadir) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Chain repair failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let Some(error) = report.error else {
+        println!("Chain is valid, nothing to repair ({} blocks)", report.kept);
+        return;
+    };
+    println!("Inconsistent history found at {}", error);
+    println!("__________________");
+    println!();
+    println!("Blocks kept: {}", report.kept);
+    println!("Blocks dropped: {}", report.dropped);
+    if let Some(backup_path) = report.backup_path {
+        println!("Original blocks db saved to: {}", backup_path);
+    }
+}
+
+pub fn export_chain_from_disk(args: &clap::ArgMatches) {
+    let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let from = *args.get_one::<u64>(FLAG_FROM).unwrap();
+    let to = args.get_one::<u64>(FLAG_TO).copied();
+    let format = *args.get_one::<ChainFormat>(FLAG_FORMAT).unwrap();
+    let out = args.get_one::<String>(FLAG_OUT).unwrap();
+    if to.is_some_and(|to| to < from) {
+        eprintln!("--to must not be below --from");
+        std::process::exit(1);
+    }
+
+    let tmp_path = format!("{}.tmp", out);
+    let result = std::fs::File::create(&tmp_path)
+        .map_err(|err| err.into())
+        .and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            export_chain(datadir, from, to, format, &mut writer)
+        })
+        .and_then(|report| {
+            std::fs::rename(&tmp_path, out)?;
+            Ok(report)
+        });
+    let report = match result {
+        Ok(report) => report,
+        Err(err) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            eprintln!("Chain export failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Exported {} blocks ({} to {}) to {}",
+        report.blocks,
+        report.from.unwrap_or_default(),
+        report.to.unwrap_or_default(),
+        out
+    );
+}
+
+pub fn import_chain_to_disk(args: &clap::ArgMatches) {
+    let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let file = args.get_one::<String>(FLAG_FILE).unwrap();
+    let report = match import_chain(datadir, file, |imported, number| {
+        if imported % IMPORT_PROGRESS_EVERY == 0 {
+            println!("Imported {} blocks, at block {}", imported, number);
+        }
+    }) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Chain import failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Imported {} blocks from {} ({})",
+        report.imported, file, report.format
+    );
+    if let Some(number) = report.latest_block_number {
+        println!(
+            "Latest block: {} ({})",
+            number,
+            HEXLOWER.encode(&report.latest_block_hash)
+        );
+    }
+    if let Some(err) = report.error {
+        eprintln!("Chain import stopped at {}", err);
+        std::process::exit(1);
+    }
+}
+
+pub fn prune_chain_on_disk(args: &clap::ArgMatches) {
+    let datadir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let keep = args.get_one::<u64>(FLAG_KEEP).copied();
+    let report = match prune_data_dir(datadir, keep) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Chain pruning failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let Some(report) = report else {
+        println!("Nothing to prune");
+        return;
+    };
+    println!("Pruned block bodies up to block {}", report.anchor);
+    println!("__________________");
+    println!();
+    println!("Bodies dropped: {}", report.pruned);
+    println!("Bodies kept: {}", report.kept);
+}
@@ -0,0 +1,94 @@
+use data_encoding::HEXLOWER;
+use tbb::database::{
+    init_data_dir, new_account, Amount, ChainSpec, DatabaseError, StorageBackend, StorageConfig,
+};
+
+const FLAG_DATA_DIR: &str = "datadir";
+const FLAG_GENESIS: &str = "genesis";
+const FLAG_ALLOC: &str = "alloc";
+const FLAG_CHAIN_ID: &str = "chain-id";
+const FLAG_STORAGE: &str = "storage";
+
+pub fn init_cmd() -> clap::Command {
+    clap::Command::new("init")
+        .about("Create a new data directory from a genesis file")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_GENESIS)
+                .long("genesis")
+                .help("Chain spec to start from, defaults to the built-in genesis")
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_ALLOC)
+                .long("alloc")
+                .help("Initial balance as account=amount, replaces any allocation of the account")
+                .action(clap::ArgAction::Append)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_CHAIN_ID)
+                .long("chain-id")
+                .help("Overrides the chain id of the genesis file")
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_STORAGE)
+                .long("storage")
+                .help("Storage backend for blocks and state: flat_file, kv or memory")
+                .default_value("flat_file")
+                .value_parser(clap::value_parser!(StorageBackend))
+                .num_args(1),
+        )
+}
+
+pub fn init_data_dir_from_args(args: &clap::ArgMatches) {
+    let data_dir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let storage = StorageConfig {
+        backend: *args.get_one::<StorageBackend>(FLAG_STORAGE).unwrap(),
+    };
+    let genesis =
+        match build_chain_spec(args).and_then(|spec| init_data_dir(data_dir, &spec, storage)) {
+            Ok(genesis) => genesis,
+            Err(err) => {
+                eprintln!("Error initializing {}: {}", data_dir, err);
+                std::process::exit(1);
+            }
+        };
+    println!("Initialized {}", data_dir);
+    println!("__________________");
+    println!();
+    println!("Chain id: {}", genesis.chain_id());
+    println!("Storage: {}", storage.backend);
+    println!("Genesis hash: {}", HEXLOWER.encode(&genesis.hash()));
+    println!("Genesis supply: {} TBB", genesis.supply());
+    for (account, balance) in genesis.get_balances().iter() {
+        println!("{}: {}", account, balance);
+    }
+}
+
+fn build_chain_spec(args: &clap::ArgMatches) -> Result<ChainSpec, DatabaseError> {
+    let mut spec = match args.get_one::<String>(FLAG_GENESIS) {
+        Some(path) => ChainSpec::load(path)?,
+        None => ChainSpec::default_spec()?,
+    };
+    if let Some(chain_id) = args.get_one::<String>(FLAG_CHAIN_ID) {
+        spec.chain_id = chain_id.clone();
+    }
+    for alloc in args.get_many::<String>(FLAG_ALLOC).into_iter().flatten() {
+        let (account, amount) = alloc.split_once('=').ok_or_else(|| {
+            DatabaseError::BadGenesis(format!("--alloc {} is not account=amount", alloc))
+        })?;
+        let amount = amount
+            .parse::<Amount>()
+            .map_err(|err| DatabaseError::BadGenesis(format!("--alloc {}: {}", alloc, err)))?;
+        spec.balances.insert(new_account(account.trim()), amount);
+    }
+    Ok(spec)
+}
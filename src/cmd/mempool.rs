@@ -0,0 +1,114 @@
+use tbb::client::{BlockingClient, ClientBuilder, ClientError};
+use tbb::node::MempoolQuery;
+
+const FLAG_NODE: &str = "node";
+const FLAG_OFFSET: &str = "offset";
+const FLAG_LIMIT: &str = "limit";
+const FLAG_TX_HASH: &str = "tx_hash";
+const FLAG_ADMIN_TOKEN: &str = "admin-token";
+const DEFAULT_NODE: &str = "http://127.0.0.1:8080";
+
+pub fn mempool_cmd() -> clap::Command {
+    clap::Command::new("mempool")
+        .about("Inspect the mempool of a running node")
+        .subcommand(mempool_list_cmd())
+        .subcommand(mempool_drop_cmd())
+}
+
+fn node_arg() -> clap::Arg {
+    clap::Arg::new(FLAG_NODE)
+        .long("node")
+        .help("URL of the running node")
+        .default_value(DEFAULT_NODE)
+        .num_args(1)
+}
+
+fn mempool_list_cmd() -> clap::Command {
+    clap::Command::new("list")
+        .about("List pending transactions, highest fee first")
+        .arg(node_arg())
+        .arg(
+            clap::Arg::new(FLAG_OFFSET)
+                .long("offset")
+                .help("Number of transactions to skip")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_LIMIT)
+                .long("limit")
+                .help("Maximum number of transactions to show")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("100")
+                .num_args(1),
+        )
+}
+
+fn mempool_drop_cmd() -> clap::Command {
+    clap::Command::new("drop")
+        .about("Remove a pending transaction and its descendants from the mempool")
+        .arg(
+            clap::Arg::new(FLAG_TX_HASH)
+                .help("Hash of the transaction to drop")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(node_arg())
+        .arg(
+            clap::Arg::new(FLAG_ADMIN_TOKEN)
+                .long("admin-token")
+                .help("Admin token the node was started with")
+                .required(true)
+                .num_args(1),
+        )
+}
+
+pub fn list_mempool(args: &clap::ArgMatches) {
+    let node = args.get_one::<String>(FLAG_NODE).unwrap();
+    let offset = args.get_one::<usize>(FLAG_OFFSET).unwrap();
+    let limit = args.get_one::<usize>(FLAG_LIMIT).unwrap();
+    let query = MempoolQuery {
+        offset: Some(*offset),
+        limit: Some(*limit),
+    };
+    let res = exit_on_error(client(ClientBuilder::new(node)).mempool(&query));
+
+    println!("Pending txs: {} ({} bytes)", res.total_txs, res.total_bytes);
+    println!("__________________");
+    println!();
+    for tx in res.txs {
+        println!(
+            "{} {} -> {} value {} fee {} nonce {} ({} bytes)",
+            tx.hash, tx.from, tx.to, tx.value, tx.fee, tx.nonce, tx.size
+        );
+    }
+    println!();
+    println!("Fee histogram:");
+    for bucket in res.fee_histogram {
+        println!("{}-{}: {}", bucket.min_fee, bucket.max_fee, bucket.count);
+    }
+}
+
+pub fn drop_mempool_tx(args: &clap::ArgMatches) {
+    let node = args.get_one::<String>(FLAG_NODE).unwrap();
+    let tx_hash = args.get_one::<String>(FLAG_TX_HASH).unwrap();
+    let admin_token = args.get_one::<String>(FLAG_ADMIN_TOKEN).unwrap();
+    let client = client(ClientBuilder::new(node).admin_token(admin_token));
+    exit_on_error(client.drop_mempool_tx(tx_hash));
+    println!("Dropped tx {} from the mempool", tx_hash);
+}
+
+fn client(builder: ClientBuilder) -> BlockingClient {
+    exit_on_error(builder.build_blocking())
+}
+
+fn exit_on_error<T>(res: Result<T, ClientError>) -> T {
+    match res {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,6 +1,14 @@
 mod balances;
+mod chain;
+mod init;
+mod mempool;
 mod run;
+mod snapshot;
 mod tx;
 pub use balances::*;
+pub use chain::*;
+pub use init::*;
+pub use mempool::*;
 pub use run::*;
+pub use snapshot::*;
 pub use tx::*;
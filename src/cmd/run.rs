@@ -1,25 +1,66 @@
-use crate::{cmd, node};
+use crate::cmd;
+use data_encoding::HEXLOWER;
+use tbb::{
+    database::{dev_accounts, dev_chain_spec, Config, DatabaseError, Genesis, DEV_ACCOUNT_BALANCE},
+    node::{NodeBuilder, SealMode},
+};
 
 pub fn run_cmd() {
     let command = clap::Command::new("Tbb")
         .version("1.0")
         .subcommand_required(true)
         .about("Does awesome things")
+        .subcommand(cmd::init_cmd())
         .subcommand(cmd::balances_cmd())
+        .subcommand(cmd::chain_cmd())
+        .subcommand(cmd::snapshot_cmd())
         .subcommand(cmd::tx_cmd())
+        .subcommand(cmd::mempool_cmd())
         .subcommand(cmd::run_http_cmd())
         .get_matches();
 
     match command.subcommand() {
+        Some(("init", args)) => cmd::init_data_dir_from_args(args),
         Some(("balances", args)) => {
             let subcommand = args.subcommand();
             if subcommand.is_none() {
                 println!("Balances command, no subcommand");
                 return;
             }
-            if let Some(("list", args)) = subcommand {
-                cmd::get_database_state_from_disk(args);
-                println!("Balances command, list subcommand");
+            match subcommand {
+                Some(("list", args)) => {
+                    cmd::get_database_state_from_disk(args);
+                    println!("Balances command, list subcommand");
+                }
+                Some(("supply", args)) => cmd::print_supply_from_disk(args),
+                _ => {}
+            }
+        }
+        Some(("chain", args)) => {
+            let subcommand = args.subcommand();
+            if subcommand.is_none() {
+                println!("Chain command, no subcommand");
+                return;
+            }
+            match subcommand {
+                Some(("verify", args)) => cmd::verify_chain_on_disk(args),
+                Some(("repair", args)) => cmd::repair_chain_on_disk(args),
+                Some(("export", args)) => cmd::export_chain_from_disk(args),
+                Some(("import", args)) => cmd::import_chain_to_disk(args),
+                Some(("prune", args)) => cmd::prune_chain_on_disk(args),
+                _ => {}
+            }
+        }
+        Some(("snapshot", args)) => {
+            let subcommand = args.subcommand();
+            if subcommand.is_none() {
+                println!("Snapshot command, no subcommand");
+                return;
+            }
+            match subcommand {
+                Some(("export", args)) => cmd::export_snapshot_from_disk(args),
+                Some(("import", args)) => cmd::import_snapshot_to_disk(args),
+                _ => {}
             }
         }
         Some(("tx", args)) => {
@@ -32,21 +73,85 @@ pub fn run_cmd() {
                 cmd::add_new_tx(tx_args);
             }
         }
-        Some(("run", args)) => {
-            let datadir = args.get_one::<String>("datadir");
-            if let Some(data_dir) = datadir {
-                println!("Run command");
-                let _ = node::run(data_dir);
-            } else {
-                println!("No datadir provided");
+        Some(("mempool", args)) => {
+            let subcommand = args.subcommand();
+            if subcommand.is_none() {
+                println!("Mempool command, no subcommand");
+                return;
+            }
+            match subcommand {
+                Some(("list", args)) => cmd::list_mempool(args),
+                Some(("drop", args)) => cmd::drop_mempool_tx(args),
+                _ => {}
             }
         }
+        Some(("run", args)) => run_node(args),
         _ => {
             println!("No command");
         }
     }
 }
 
+fn run_node(args: &clap::ArgMatches) {
+    let miner = args.get_one::<String>("miner").unwrap();
+    let admin_token = args.get_one::<String>("admin-token");
+    let mut builder = if args.get_flag("dev") {
+        println!("Run command (dev mode, in-memory)");
+        match dev_node_builder(args) {
+            Ok(builder) => builder,
+            Err(err) => {
+                eprintln!("Error running dev node: {}", err);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(data_dir) = args.get_one::<String>("datadir") {
+        println!("Run command");
+        NodeBuilder::data_dir(data_dir)
+    } else {
+        println!("No datadir provided");
+        return;
+    };
+    if !miner.is_empty() {
+        builder = builder.miner(miner);
+    }
+    if let Some(admin_token) = admin_token {
+        builder = builder.admin_token(admin_token);
+    }
+    builder = builder.allow_unsigned(args.get_flag("allow-unsigned"));
+    std::env::set_var("RUST_LOG", "debug");
+    env_logger::init();
+    if let Err(err) = builder.run() {
+        eprintln!("Error running node: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn dev_node_builder(args: &clap::ArgMatches) -> Result<NodeBuilder, DatabaseError> {
+    let accounts = dev_accounts(*args.get_one::<usize>("dev-accounts").unwrap())?;
+    let seal_interval = *args.get_one::<u64>("seal-interval").unwrap();
+    let spec = dev_chain_spec(&accounts, seal_interval)?;
+    let genesis = Genesis::from_spec(spec.clone())?;
+    println!("Dev chain: {}", genesis.chain_id());
+    println!("Genesis hash: {}", HEXLOWER.encode(&genesis.hash()));
+    for (index, dev) in accounts.iter().enumerate() {
+        println!("Dev account {}: {}", index, dev.account);
+        println!("  secret key: {}", dev.secret_key);
+        println!("  balance: {} TBB", DEV_ACCOUNT_BALANCE);
+    }
+    let seal = if seal_interval > 0 {
+        println!("Sealing a block every {}s", seal_interval);
+        SealMode::Interval
+    } else {
+        println!("Sealing a block for every tx");
+        SealMode::Instant
+    };
+    let mut builder = NodeBuilder::in_memory(spec, Config::default()).seal(seal);
+    if let Some(dev) = accounts.first() {
+        builder = builder.miner(&dev.account);
+    }
+    Ok(builder)
+}
+
 pub fn run_http_cmd() -> clap::Command {
     clap::Command::new("run")
         .version("1.0")
@@ -56,7 +161,51 @@ pub fn run_http_cmd() -> clap::Command {
             clap::Arg::new("datadir")
                 .long("datadir")
                 .help("The directory to store the database")
-                .required(true)
+                .required_unless_present("dev")
+                .conflicts_with("dev")
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new("dev")
+                .long("dev")
+                .help("Run a throwaway in-memory chain with pre-funded dev accounts")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("dev-accounts")
+                .long("dev-accounts")
+                .help("Number of pre-funded dev accounts")
+                .conflicts_with("datadir")
+                .default_value("10")
+                .value_parser(clap::value_parser!(usize))
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new("seal-interval")
+                .long("seal-interval")
+                .help("Seal a block every N seconds instead of for every tx")
+                .conflicts_with("datadir")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new("miner")
+                .long("miner")
+                .help("Account credited with the fees of produced blocks, defaults to dev account 0 with --dev")
+                .default_value("")
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new("allow-unsigned")
+                .long("allow-unsigned")
+                .help("Accept unsigned txs from accounts without a key, for local development only")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("admin-token")
+                .long("admin-token")
+                .help("Bearer token required by admin endpoints such as DELETE /mempool/{tx_hash}")
                 .num_args(1),
         )
 }
@@ -0,0 +1,123 @@
+use data_encoding::HEXLOWER;
+use tbb::database::{
+    export_snapshot, init_data_dir_from_snapshot, SnapshotArchive, StorageBackend, StorageConfig,
+};
+
+const FLAG_DATA_DIR: &str = "datadir";
+const FLAG_AT: &str = "at";
+const FLAG_OUT: &str = "out";
+const FLAG_FILE: &str = "file";
+const FLAG_STORAGE: &str = "storage";
+
+pub fn snapshot_cmd() -> clap::Command {
+    let export_cmd = snapshot_export_cmd();
+    let import_cmd = snapshot_import_cmd();
+
+    clap::Command::new("snapshot")
+        .about("Export and import state snapshots")
+        .subcommand(export_cmd)
+        .subcommand(import_cmd)
+}
+
+fn snapshot_export_cmd() -> clap::Command {
+    clap::Command::new("export")
+        .about("Write the state at a block height to a snapshot file")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_AT)
+                .long("at")
+                .help("Height of the block the snapshot is anchored to")
+                .required(true)
+                .value_parser(clap::value_parser!(u64))
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_OUT)
+                .long("out")
+                .help("Snapshot file to write")
+                .required(true)
+                .num_args(1),
+        )
+}
+
+fn snapshot_import_cmd() -> clap::Command {
+    clap::Command::new("import")
+        .about("Create a new data directory from a snapshot file")
+        .arg(
+            clap::Arg::new(FLAG_DATA_DIR)
+                .long("datadir")
+                .help("data directory")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_FILE)
+                .help("Snapshot file written by `tbb snapshot export`")
+                .required(true)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_STORAGE)
+                .long("storage")
+                .help("Storage backend for the new data directory: flat_file or kv")
+                .default_value("flat_file")
+                .value_parser(clap::value_parser!(StorageBackend))
+                .num_args(1),
+        )
+}
+
+pub fn export_snapshot_from_disk(args: &clap::ArgMatches) {
+    let data_dir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let at = *args.get_one::<u64>(FLAG_AT).unwrap();
+    let out = args.get_one::<String>(FLAG_OUT).unwrap();
+    let archive = match export_snapshot(data_dir, at).and_then(|archive| {
+        archive.write(out)?;
+        Ok(archive)
+    }) {
+        Ok(archive) => archive,
+        Err(err) => {
+            eprintln!("Snapshot export failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    println!("Exported snapshot to {}", out);
+    print_archive(&archive);
+}
+
+pub fn import_snapshot_to_disk(args: &clap::ArgMatches) {
+    let data_dir = args.get_one::<String>(FLAG_DATA_DIR).unwrap();
+    let file = args.get_one::<String>(FLAG_FILE).unwrap();
+    let storage = StorageConfig {
+        backend: *args.get_one::<StorageBackend>(FLAG_STORAGE).unwrap(),
+    };
+    let archive = match SnapshotArchive::load(file).and_then(|archive| {
+        init_data_dir_from_snapshot(data_dir, &archive, storage)?;
+        Ok(archive)
+    }) {
+        Ok(archive) => archive,
+        Err(err) => {
+            eprintln!("Snapshot import failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    println!("Initialized {} from {}", data_dir, file);
+    print_archive(&archive);
+}
+
+fn print_archive(archive: &SnapshotArchive) {
+    let snapshot = &archive.contents.snapshot;
+    println!("__________________");
+    println!();
+    println!("Chain id: {}", archive.chain_id());
+    println!("Genesis hash: {}", HEXLOWER.encode(&snapshot.genesis_hash));
+    println!("Block: {}", archive.block_number());
+    println!("Block hash: {}", HEXLOWER.encode(&archive.block_hash()));
+    println!("Accounts: {}", snapshot.balances.len());
+    println!("Checksum: {}", archive.checksum);
+}
@@ -1,12 +1,14 @@
-use std::time;
-
-use crate::database::{new_account, Block, Hash, State, Tx};
+use tbb::database::{new_account, Amount, State, Tx};
 
 const FLAG_FROM: &str = "from";
 const FLAG_TO: &str = "to";
 const FLAG_VALUE: &str = "value";
+const FLAG_FEE: &str = "fee";
+const FLAG_NONCE: &str = "nonce";
+const FLAG_MINER: &str = "miner";
 const FLAG_DATA: &str = "data";
 const FLAG_DATA_DIR: &str = "datadir";
+const FLAG_CHAIN_ID: &str = "chain-id";
 
 pub fn tx_cmd() -> clap::Command {
     let tx_add_cmd = tx_add_cmd();
@@ -47,10 +49,38 @@ fn tx_add_cmd() -> clap::Command {
                 .required(true)
                 .num_args(1),
         )
+        .arg(
+            clap::Arg::new(FLAG_FEE)
+                .long("fee")
+                .help("Fee paid to the block producer")
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_NONCE)
+                .long("nonce")
+                .help("Sender nonce, defaults to the next unused one")
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_MINER)
+                .long("miner")
+                .help("Account credited with the fees of the new block")
+                .required(false)
+                .num_args(1),
+        )
         .arg(
             clap::Arg::new(FLAG_DATA)
                 .long("data")
-                .help("Data")
+                .help("Free-form memo attached to the transaction")
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            clap::Arg::new(FLAG_CHAIN_ID)
+                .long("chain-id")
+                .help("Chain the transaction is valid on, defaults to the datadir's chain")
                 .required(false)
                 .num_args(1),
         )
@@ -60,63 +90,75 @@ pub fn add_new_tx(tx_args: &clap::ArgMatches) {
     let from = tx_args.get_one::<String>(FLAG_FROM).unwrap();
     let to = tx_args.get_one::<String>(FLAG_TO).unwrap();
     let data_dir = tx_args.get_one::<String>(FLAG_DATA_DIR).unwrap();
-    let value = tx_args
-        .get_one::<String>(FLAG_VALUE)
-        .unwrap()
-        .parse::<u64>()
-        .unwrap();
-    let data = match tx_args
-        .get_one::<String>(FLAG_DATA)
-        .unwrap_or(&String::from(""))
-        .as_str()
-    {
-        "reward" => "reward".to_string(),
-        _ => "".to_owned(),
+    let value = tx_args.get_one::<String>(FLAG_VALUE).unwrap();
+    let value = match value.parse::<Amount>() {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Invalid --value {}: {}", value, err);
+            std::process::exit(1);
+        }
+    };
+    let data = match tx_args.get_one::<String>(FLAG_DATA) {
+        Some(data) => data.clone(),
+        None => "".to_string(),
     };
     let from_account = new_account(from);
     let to_account = new_account(to);
 
-    let tx = Tx::new(from_account, to_account, &value, &data);
-    let txs = vec![
-        tx.clone(),
-        Tx::new("cerf".to_string(), "babayaga".to_string(), &2000, ""),
-        Tx::new("cerf".to_string(), "andrej".to_string(), &100, "reward"),
-        Tx::new("cerf".to_string(), "caesar".to_string(), &1000, ""),
-    ];
-    let mut state = State::new_state_from_disk(data_dir);
-    state.close();
-    let parent: Hash = [0; 32];
-    let block = Block::new(
-        parent,
-        time::SystemTime::now()
-            .duration_since(time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
-        txs.clone(),
-        0,
-    );
-    if let Err(err) = state.add_block(block) {
-        panic!("Error adding block to state: {}", err);
+    let mut state = match State::new_state_from_disk(data_dir) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("Error loading state: {}", err);
+            std::process::exit(1);
+        }
     };
-    let Ok(block0hash) = state.persist() else {
-        panic!("Error persisting block 0 to disk");
+    let fee = match tx_args.get_one::<String>(FLAG_FEE) {
+        Some(fee) => match fee.parse::<Amount>() {
+            Ok(fee) => fee,
+            Err(err) => {
+                eprintln!("Invalid --fee {}: {}", fee, err);
+                std::process::exit(1);
+            }
+        },
+        None => state.consensus().fees.min_tx_fee,
     };
-
-    let block1 = Block::new(
-        block0hash,
-        time::SystemTime::now()
-            .duration_since(time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
-        vec![
-            Tx::new("cerf".to_string(), "babayaga".to_string(), &2000, ""),
-            Tx::new("cerf".to_string(), "andrej".to_string(), &100, "reward"),
-        ],
-        1,
+    let nonce = match tx_args.get_one::<String>(FLAG_NONCE) {
+        Some(nonce) => match nonce.parse::<u64>() {
+            Ok(nonce) => nonce,
+            Err(err) => {
+                eprintln!("Invalid --nonce {}: {}", nonce, err);
+                std::process::exit(1);
+            }
+        },
+        None => state.next_pending_nonce(&from_account),
+    };
+    let chain_id = match tx_args.get_one::<String>(FLAG_CHAIN_ID) {
+        Some(chain_id) => chain_id.clone(),
+        None => state.chain_id().to_string(),
+    };
+    let tx = Tx::new(
+        &chain_id,
+        from_account,
+        to_account,
+        value,
+        fee,
+        nonce,
+        &data,
     );
-    let _ = state.add_block(block1);
+    if let Some(miner) = tx_args.get_one::<String>(FLAG_MINER) {
+        state.set_miner(new_account(miner));
+    }
+    if let Err(err) = state.add_tx(&tx) {
+        eprintln!("Error adding transaction to state: {}", err);
+        std::process::exit(1);
+    };
     if let Err(err) = state.persist() {
-        panic!("Error persisting block 1 to disk {}", err);
+        eprintln!("Error persisting block to disk: {}", err);
+        std::process::exit(1);
+    };
+    if let Err(err) = state.close() {
+        eprintln!("Error syncing state to disk: {}", err);
+        std::process::exit(1);
     };
     println!("TX successfully added to the ledger");
 }
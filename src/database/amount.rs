@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+/// A number of TBB tokens. Arithmetic is checked, balances never wrap.
+#[serde(transparent)]
+pub struct Amount(u128);
+
+impl Amount {
+    /// No tokens.
+    pub const ZERO: Amount = Amount(0);
+
+    /// `value` tokens.
+    pub const fn new(value: u128) -> Amount {
+        Amount(value)
+    }
+    /// The number of tokens.
+    pub fn value(&self) -> u128 {
+        self.0
+    }
+    /// True for [`Amount::ZERO`].
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+    /// `self + other`, or `None` on overflow.
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+    /// `self - other`, or `None` if `other` is larger.
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+}
+
+impl From<u64> for Amount {
+    fn from(value: u64) -> Self {
+        Amount(value as u128)
+    }
+}
+
+impl std::str::FromStr for Amount {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u128>().map(Amount)
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
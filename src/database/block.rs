@@ -1,7 +1,12 @@
-use super::Tx;
+use super::{Account, DatabaseError, Tx};
 
+/// SHA-256 digest identifying blocks, txs and chain specs.
 pub type Hash = [u8; 32];
 
+/// A block of transactions on top of its parent.
+///
+/// The block hash covers the header and every tx including its signature, so
+/// changing anything in a sealed block changes its hash.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct Block {
     header: BlockHeader,
@@ -10,54 +15,110 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn new(parent: Hash, time: u64, tx: Vec<Tx>, number: u64) -> Self {
+    /// Builds an unsealed block. The `number` must follow the parent's and
+    /// `chain_id` must match the chain for [`State::add_block`](crate::State::add_block) to accept it.
+    pub fn new(
+        chain_id: &str,
+        parent: Hash,
+        time: u64,
+        tx: Vec<Tx>,
+        number: u64,
+        miner: Account,
+    ) -> Self {
         Self {
             header: BlockHeader {
+                chain_id: chain_id.to_string(),
                 parent,
                 time,
                 number,
+                miner,
             },
             tx,
         }
     }
-    pub fn hash(&self) -> Result<Hash, String> {
-        let Ok(block_json) = serde_json::to_string(&self) else {
-            return Err("failed to serialize block".to_string());
-        };
+    /// SHA-256 of the block's JSON encoding.
+    pub fn hash(&self) -> Result<Hash, DatabaseError> {
+        let block_json = serde_json::to_string(&self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
         let hash = ring::digest::digest(&ring::digest::SHA256, block_json.as_bytes());
         let mut result = [0u8; 32];
         result.copy_from_slice(hash.as_ref());
         Ok(result)
     }
 
+    /// Size of the block's JSON encoding in bytes, checked against
+    /// `limits.max_block_bytes`.
+    pub fn size(&self) -> Result<usize, DatabaseError> {
+        let block_json = serde_json::to_vec(&self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        Ok(block_json.len())
+    }
+
+    /// The block's header.
     pub fn header(&self) -> &BlockHeader {
         &self.header
     }
+    /// Txs in the order they were applied, coinbase first if there is one.
     pub fn txs(&self) -> &Vec<Tx> {
         &self.tx
     }
 }
+/// Chain id, parent hash, height, timestamp and miner of a block.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct BlockHeader {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    chain_id: String,
     parent: Hash,
     number: u64,
     time: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    miner: Account,
 }
 
 impl BlockHeader {
+    /// Chain the block belongs to. Empty for blocks that predate chain ids.
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+    /// Hash of the previous block, or the genesis hash for block 0.
     pub fn parent(&self) -> &Hash {
         &self.parent
     }
+    /// Height of the block, starting at 0.
     pub fn number(&self) -> u64 {
         self.number
     }
+    /// Unix time in seconds when the block was sealed.
     pub fn time(&self) -> u64 {
         self.time
     }
+    /// Account credited with the block reward and fees.
+    pub fn miner(&self) -> &Account {
+        &self.miner
+    }
 }
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct BlockFS {
+pub(crate) struct BlockFS {
     pub key: Hash,
     #[serde(rename = "block")]
     pub value: Block,
 }
+
+impl BlockFS {
+    pub fn verify_key(&self) -> Result<Hash, DatabaseError> {
+        let computed = self.value.hash()?;
+        if computed != self.key {
+            return Err(DatabaseError::KeyMismatch {
+                stored: self.key,
+                computed,
+            });
+        }
+        Ok(computed)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct HeaderFS {
+    pub key: Hash,
+    pub header: BlockHeader,
+}
@@ -0,0 +1,33 @@
+use std::io::Write;
+
+use super::{DatabaseError, MempoolConfig, PruningConfig, SnapshotConfig, StorageConfig};
+
+/// Node settings from the datadir's `config.json`. Unlike the
+/// [`ChainSpec`](crate::ChainSpec) they are local and may differ between nodes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mempool: MempoolConfig,
+    pub snapshots: SnapshotConfig,
+    pub pruning: PruningConfig,
+    pub storage: StorageConfig,
+}
+
+impl Config {
+    /// Reads a config file. Missing fields take their defaults.
+    pub fn load(path: &str) -> Result<Config, DatabaseError> {
+        let data = std::fs::read_to_string(path)?;
+        serde_json::from_str(&data)
+            .map_err(|err| DatabaseError::Corrupt(format!("config {}: {}", path, err)))
+    }
+
+    /// Writes the config as pretty-printed JSON.
+    pub fn write(&self, path: &str) -> Result<(), DatabaseError> {
+        let config_json = serde_json::to_string_pretty(self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        let mut file = std::fs::File::create(path)?;
+        writeln!(file, "{}", config_json)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+use super::{Account, Amount};
+
+pub(crate) const DEFAULT_BLOCK_INTERVAL_SECS: u64 = 10;
+pub(crate) const DEFAULT_MAX_BLOCK_TXS: usize = 1000;
+pub(crate) const DEFAULT_MAX_BLOCK_BYTES: usize = 1024 * 1024;
+pub(crate) const DEFAULT_MAX_TX_DATA_BYTES: usize = 256;
+pub(crate) const DEFAULT_MIN_TX_FEE: Amount = Amount::new(1);
+pub(crate) const DEFAULT_INITIAL_BLOCK_REWARD: Amount = Amount::new(100);
+pub(crate) const DEFAULT_HALVING_INTERVAL: u64 = 210_000;
+pub(crate) const DEFAULT_MAX_SUPPLY: Amount = Amount::new(21_000_000);
+
+/// Who may produce blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusEngine {
+    /// Any account.
+    #[default]
+    Open,
+    /// Only the accounts listed in `validators`.
+    ProofOfAuthority,
+}
+
+/// The `consensus` section of a chain spec.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    #[serde(rename = "type")]
+    pub engine: ConsensusEngine,
+    pub validators: Vec<Account>,
+    pub block_interval_secs: u64,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            engine: ConsensusEngine::Open,
+            validators: Vec::new(),
+            block_interval_secs: DEFAULT_BLOCK_INTERVAL_SECS,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Whether `miner` may produce blocks.
+    pub fn is_producer(&self, miner: &Account) -> bool {
+        match self.engine {
+            ConsensusEngine::Open => true,
+            ConsensusEngine::ProofOfAuthority => self.validators.contains(miner),
+        }
+    }
+}
+
+/// The `limits` section of a chain spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockLimits {
+    pub max_block_txs: usize,
+    pub max_block_bytes: usize,
+    pub max_tx_data_bytes: usize,
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        BlockLimits {
+            max_block_txs: DEFAULT_MAX_BLOCK_TXS,
+            max_block_bytes: DEFAULT_MAX_BLOCK_BYTES,
+            max_tx_data_bytes: DEFAULT_MAX_TX_DATA_BYTES,
+        }
+    }
+}
+
+/// The `fees` section of a chain spec. Burned fees leave the supply
+/// instead of going to the miner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeRules {
+    pub min_tx_fee: Amount,
+    pub burn_fees: bool,
+}
+
+impl Default for FeeRules {
+    fn default() -> Self {
+        FeeRules {
+            min_tx_fee: DEFAULT_MIN_TX_FEE,
+            burn_fees: false,
+        }
+    }
+}
+
+/// The `rewards` section of a chain spec. The block reward halves every
+/// `halving_interval` blocks and issuance stops at `max_supply`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RewardSchedule {
+    pub initial_block_reward: Amount,
+    pub halving_interval: u64,
+    pub max_supply: Amount,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        RewardSchedule {
+            initial_block_reward: DEFAULT_INITIAL_BLOCK_REWARD,
+            halving_interval: DEFAULT_HALVING_INTERVAL,
+            max_supply: DEFAULT_MAX_SUPPLY,
+        }
+    }
+}
+
+impl RewardSchedule {
+    /// Reward for `block_number` before the `max_supply` cap.
+    pub fn scheduled_reward(&self, block_number: u64) -> Amount {
+        if self.halving_interval == 0 {
+            return self.initial_block_reward;
+        }
+        let halvings = block_number / self.halving_interval;
+        let reward = u32::try_from(halvings)
+            .ok()
+            .and_then(|halvings| self.initial_block_reward.value().checked_shr(halvings))
+            .unwrap_or(0);
+        Amount::new(reward)
+    }
+    /// Height of the next halving after `block_number`, `None` once the
+    /// scheduled reward is zero.
+    pub fn next_halving_height(&self, block_number: u64) -> Option<u64> {
+        if self.halving_interval == 0 || self.scheduled_reward(block_number).is_zero() {
+            return None;
+        }
+        (block_number / self.halving_interval + 1).checked_mul(self.halving_interval)
+    }
+}
+
+/// All consensus rules of a chain, taken from its [`ChainSpec`](crate::ChainSpec).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsensusConfig {
+    pub engine: EngineConfig,
+    pub limits: BlockLimits,
+    pub fees: FeeRules,
+    pub rewards: RewardSchedule,
+}
+
+impl ConsensusConfig {
+    /// Checks the rules are consistent, e.g. that PoA has validators.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.engine.block_interval_secs == 0 {
+            return Err("consensus.block_interval_secs must be greater than 0".to_string());
+        }
+        match self.engine.engine {
+            ConsensusEngine::Open if !self.engine.validators.is_empty() => {
+                return Err(
+                    "consensus.validators are only allowed with proof_of_authority".to_string(),
+                );
+            }
+            ConsensusEngine::ProofOfAuthority if self.engine.validators.is_empty() => {
+                return Err("proof_of_authority needs at least one validator".to_string());
+            }
+            _ => {}
+        }
+        for (index, validator) in self.engine.validators.iter().enumerate() {
+            if validator.is_empty() {
+                return Err(format!("consensus.validators[{}] is empty", index));
+            }
+            if self.engine.validators[..index].contains(validator) {
+                return Err(format!("validator {} is listed twice", validator));
+            }
+        }
+        if self.limits.max_block_txs == 0 || self.limits.max_block_bytes == 0 {
+            return Err(
+                "limits.max_block_txs and limits.max_block_bytes must be greater than 0"
+                    .to_string(),
+            );
+        }
+        if self.limits.max_tx_data_bytes >= self.limits.max_block_bytes {
+            return Err(format!(
+                "limits.max_tx_data_bytes {} must be below limits.max_block_bytes {}",
+                self.limits.max_tx_data_bytes, self.limits.max_block_bytes
+            ));
+        }
+        if self.rewards.initial_block_reward > self.rewards.max_supply {
+            return Err(format!(
+                "rewards.initial_block_reward {} exceeds rewards.max_supply {}",
+                self.rewards.initial_block_reward, self.rewards.max_supply
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(halving_interval: u64) -> RewardSchedule {
+        RewardSchedule {
+            initial_block_reward: Amount::new(100),
+            halving_interval,
+            max_supply: DEFAULT_MAX_SUPPLY,
+        }
+    }
+
+    #[test]
+    fn rewards_halve_at_each_interval_boundary() {
+        let rewards = schedule(10);
+        for (block_number, reward, next_halving) in [
+            (0, 100, Some(10)),
+            (9, 100, Some(10)),
+            (10, 50, Some(20)),
+            (19, 50, Some(20)),
+            (20, 25, Some(30)),
+            (30, 12, Some(40)),
+            (60, 1, Some(70)),
+            (69, 1, Some(70)),
+            (70, 0, None),
+            (u64::MAX, 0, None),
+        ] {
+            assert_eq!(
+                rewards.scheduled_reward(block_number),
+                Amount::new(reward),
+                "reward of block {}",
+                block_number
+            );
+            assert_eq!(
+                rewards.next_halving_height(block_number),
+                next_halving,
+                "next halving after block {}",
+                block_number
+            );
+        }
+    }
+
+    #[test]
+    fn zero_halving_interval_never_halves() {
+        let rewards = schedule(0);
+        assert_eq!(rewards.scheduled_reward(u64::MAX), Amount::new(100));
+        assert_eq!(rewards.next_halving_height(0), None);
+    }
+}
@@ -0,0 +1,60 @@
+use data_encoding::HEXLOWER;
+
+use super::{Account, Amount, ChainSpec, DatabaseError, SigningKey};
+
+/// Chain id of [`dev_chain_spec`].
+pub const DEV_CHAIN_ID: &str = "tbb-dev";
+/// Genesis balance of every dev account.
+pub const DEV_ACCOUNT_BALANCE: Amount = Amount::new(1_000_000);
+
+/// A deterministic account with a published key, for tests and local
+/// networks only.
+#[derive(Debug, Clone)]
+pub struct DevAccount {
+    pub account: Account,
+    pub secret_key: String,
+}
+
+impl DevAccount {
+    /// The account's key, for signing txs.
+    pub fn signing_key(&self) -> Result<SigningKey, DatabaseError> {
+        SigningKey::from_hex(&self.secret_key)
+    }
+}
+
+/// The first `count` dev accounts. They are the same on every machine.
+pub fn dev_accounts(count: usize) -> Result<Vec<DevAccount>, DatabaseError> {
+    let mut accounts = Vec::with_capacity(count);
+    for index in 0..count {
+        let seed = ring::digest::digest(
+            &ring::digest::SHA256,
+            format!("tbb dev account {}", index).as_bytes(),
+        );
+        let key = SigningKey::from_seed(seed.as_ref())
+            .map_err(|err| DatabaseError::BadGenesis(format!("dev account {}: {}", index, err)))?;
+        accounts.push(DevAccount {
+            account: key.account().clone(),
+            secret_key: HEXLOWER.encode(seed.as_ref()),
+        });
+    }
+    Ok(accounts)
+}
+
+/// The built-in spec with chain id [`DEV_CHAIN_ID`] and `accounts` as
+/// its only balances. A non-zero `seal_interval_secs` overrides the block
+/// interval.
+pub fn dev_chain_spec(
+    accounts: &[DevAccount],
+    seal_interval_secs: u64,
+) -> Result<ChainSpec, DatabaseError> {
+    let mut spec = ChainSpec::default_spec()?;
+    spec.chain_id = DEV_CHAIN_ID.to_string();
+    if seal_interval_secs > 0 {
+        spec.consensus.block_interval_secs = seal_interval_secs;
+    }
+    spec.balances = accounts
+        .iter()
+        .map(|dev| (dev.account.clone(), DEV_ACCOUNT_BALANCE))
+        .collect();
+    Ok(spec)
+}
@@ -0,0 +1,246 @@
+use data_encoding::HEXLOWER;
+
+use super::{Account, Amount, Hash};
+
+/// Everything that can go wrong reading, validating or writing the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    InsufficientBalance {
+        account: Account,
+        balance: Amount,
+        cost: Amount,
+    },
+    InvalidAmount {
+        value: Amount,
+    },
+    Overflow {
+        account: Account,
+    },
+    FeeTooLow {
+        fee: Amount,
+        min: Amount,
+    },
+    UnknownAccount(Account),
+    InvalidNonce {
+        account: Account,
+        expected: u64,
+        found: u64,
+    },
+    DuplicateTx,
+    ReplacementUnderpriced {
+        fee: Amount,
+        current: Amount,
+    },
+    MempoolFull,
+    WrongChain {
+        expected: String,
+        found: String,
+    },
+    CoinbaseNotAllowed,
+    InvalidCoinbase(String),
+    DataTooLarge {
+        max: usize,
+        found: usize,
+    },
+    TooManyTxs {
+        max: usize,
+        found: usize,
+    },
+    BlockTooLarge {
+        max: usize,
+        found: usize,
+    },
+    InvalidBlockNumber {
+        expected: u64,
+        found: u64,
+    },
+    ParentMismatch {
+        expected: Hash,
+        found: Hash,
+    },
+    KeyMismatch {
+        stored: Hash,
+        computed: Hash,
+    },
+    UnauthorizedProducer(Account),
+    TimeBeforeParent {
+        parent: u64,
+        found: u64,
+    },
+    SupplyMismatch {
+        expected: Amount,
+        found: Amount,
+    },
+    SupplyOverflow,
+    BlockNotFound(u64),
+    AlreadyInitialized(String),
+    DataDirLocked {
+        data_dir: String,
+        pid: Option<u32>,
+    },
+    ReadOnly,
+    Io(String),
+    Corrupt(String),
+    BadGenesis(String),
+    BadSnapshot(String),
+    BadSignature(String),
+    Serialization(String),
+}
+
+impl DatabaseError {
+    /// Stable snake_case name of the error, sent as `error.code` by the API.
+    pub fn code(&self) -> &'static str {
+        match self {
+            DatabaseError::InsufficientBalance { .. } => "insufficient_funds",
+            DatabaseError::InvalidAmount { .. } => "invalid_amount",
+            DatabaseError::FeeTooLow { .. } => "fee_too_low",
+            DatabaseError::UnknownAccount(_) => "unknown_account",
+            DatabaseError::InvalidNonce { .. } => "invalid_nonce",
+            DatabaseError::DuplicateTx => "duplicate",
+            DatabaseError::ReplacementUnderpriced { .. } => "replacement_underpriced",
+            DatabaseError::MempoolFull => "mempool_full",
+            DatabaseError::WrongChain { .. } => "wrong_chain",
+            DatabaseError::CoinbaseNotAllowed => "coinbase_not_allowed",
+            DatabaseError::InvalidCoinbase(_) => "invalid_coinbase",
+            DatabaseError::Overflow { .. } => "overflow",
+            DatabaseError::DataTooLarge { .. } => "data_too_large",
+            DatabaseError::TooManyTxs { .. } => "too_many_txs",
+            DatabaseError::BlockTooLarge { .. } => "block_too_large",
+            DatabaseError::InvalidBlockNumber { .. } => "invalid_block_number",
+            DatabaseError::ParentMismatch { .. } => "parent_mismatch",
+            DatabaseError::KeyMismatch { .. } => "key_mismatch",
+            DatabaseError::UnauthorizedProducer(_) => "unauthorized_producer",
+            DatabaseError::TimeBeforeParent { .. } => "time_before_parent",
+            DatabaseError::SupplyMismatch { .. } => "supply_mismatch",
+            DatabaseError::SupplyOverflow => "supply_overflow",
+            DatabaseError::BlockNotFound(_) => "block_not_found",
+            DatabaseError::AlreadyInitialized(_) => "already_initialized",
+            DatabaseError::DataDirLocked { .. } => "datadir_locked",
+            DatabaseError::ReadOnly => "read_only",
+            DatabaseError::Io(_) => "io",
+            DatabaseError::Corrupt(_) => "corrupt",
+            DatabaseError::BadGenesis(_) => "bad_genesis",
+            DatabaseError::BadSnapshot(_) => "bad_snapshot",
+            DatabaseError::BadSignature(_) => "bad_signature",
+            DatabaseError::Serialization(_) => "serialization",
+        }
+    }
+}
+
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseError::InsufficientBalance {
+                account,
+                balance,
+                cost,
+            } => write!(
+                f,
+                "sender {} balance is {} TBB, tx cost is {} TBB",
+                account, balance, cost
+            ),
+            DatabaseError::InvalidAmount { value } => {
+                write!(f, "tx value must be greater than 0, got {}", value)
+            }
+            DatabaseError::FeeTooLow { fee, min } => {
+                write!(f, "tx fee {} TBB is below the minimum of {} TBB", fee, min)
+            }
+            DatabaseError::UnknownAccount(account) => write!(f, "unknown account {}", account),
+            DatabaseError::InvalidNonce {
+                account,
+                expected,
+                found,
+            } => write!(
+                f,
+                "next nonce of account {} is {}, got {}",
+                account, expected, found
+            ),
+            DatabaseError::DuplicateTx => write!(f, "tx is already pending"),
+            DatabaseError::ReplacementUnderpriced { fee, current } => write!(
+                f,
+                "replacement fee {} TBB must be higher than the pending fee of {} TBB",
+                fee, current
+            ),
+            DatabaseError::MempoolFull => write!(f, "mempool is full"),
+            DatabaseError::WrongChain { expected, found } => write!(
+                f,
+                "chain id {:?} does not match this chain {:?}",
+                found, expected
+            ),
+            DatabaseError::CoinbaseNotAllowed => {
+                write!(f, "coinbase txs can only be created by block producers")
+            }
+            DatabaseError::InvalidCoinbase(err) => write!(f, "invalid coinbase: {}", err),
+            DatabaseError::Overflow { account } => {
+                write!(f, "balance of account {} would overflow", account)
+            }
+            DatabaseError::DataTooLarge { max, found } => write!(
+                f,
+                "tx data is {} bytes, the maximum is {} bytes",
+                found, max
+            ),
+            DatabaseError::TooManyTxs { max, found } => {
+                write!(f, "block has {} txs, the maximum is {}", found, max)
+            }
+            DatabaseError::BlockTooLarge { max, found } => {
+                write!(f, "block is {} bytes, the maximum is {} bytes", found, max)
+            }
+            DatabaseError::InvalidBlockNumber { expected, found } => write!(
+                f,
+                "block number {} is not consecutive, expected {}",
+                found, expected
+            ),
+            DatabaseError::ParentMismatch { expected, found } => write!(
+                f,
+                "parent {} does not match previous block hash {}",
+                HEXLOWER.encode(found),
+                HEXLOWER.encode(expected)
+            ),
+            DatabaseError::KeyMismatch { stored, computed } => write!(
+                f,
+                "stored key {} does not match block hash {}",
+                HEXLOWER.encode(stored),
+                HEXLOWER.encode(computed)
+            ),
+            DatabaseError::UnauthorizedProducer(miner) => {
+                write!(f, "{:?} is not a validator of this chain", miner)
+            }
+            DatabaseError::TimeBeforeParent { parent, found } => write!(
+                f,
+                "block time {} is before previous block time {}",
+                found, parent
+            ),
+            DatabaseError::SupplyMismatch { expected, found } => write!(
+                f,
+                "balances add up to {} TBB but total supply is {} TBB",
+                found, expected
+            ),
+            DatabaseError::SupplyOverflow => write!(f, "total supply overflows"),
+            DatabaseError::BlockNotFound(number) => write!(f, "block {} not found", number),
+            DatabaseError::AlreadyInitialized(data_dir) => write!(
+                f,
+                "{} already contains a chain, refusing to overwrite it",
+                data_dir
+            ),
+            DatabaseError::DataDirLocked { data_dir, pid } => match pid {
+                Some(pid) => write!(f, "datadir {} is in use by pid {}", data_dir, pid),
+                None => write!(f, "datadir {} is in use by another process", data_dir),
+            },
+            DatabaseError::ReadOnly => write!(f, "state was opened read-only"),
+            DatabaseError::Io(err) => write!(f, "io error: {}", err),
+            DatabaseError::Corrupt(err) => write!(f, "corrupt database: {}", err),
+            DatabaseError::BadGenesis(err) => write!(f, "bad genesis: {}", err),
+            DatabaseError::BadSnapshot(err) => write!(f, "bad snapshot: {}", err),
+            DatabaseError::BadSignature(err) => write!(f, "bad signature: {}", err),
+            DatabaseError::Serialization(err) => write!(f, "serialization failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<std::io::Error> for DatabaseError {
+    fn from(err: std::io::Error) -> Self {
+        DatabaseError::Io(err.to_string())
+    }
+}
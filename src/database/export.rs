@@ -0,0 +1,355 @@
+use std::io::{BufRead, Read, Write};
+
+use super::{
+    open_data_dir_read_only, open_data_dir_stores, Block, BlockFS, DatabaseError, Hash, State,
+    VerifyError,
+};
+
+pub(crate) const CHAIN_BINARY_MAGIC: &[u8; 8] = b"TBBCHAIN";
+pub(crate) const CHAIN_BINARY_VERSION: u32 = 1;
+
+const MAX_BINARY_BLOCK_BYTES: usize = 64 * 1024 * 1024;
+
+/// File format of [`export_chain`]. [`import_chain`] detects it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainFormat {
+    /// One stored block per line.
+    Jsonl,
+    /// A `TBBCHAIN` header followed by length-prefixed blocks.
+    Binary,
+}
+
+impl std::fmt::Display for ChainFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainFormat::Jsonl => write!(f, "jsonl"),
+            ChainFormat::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+impl std::str::FromStr for ChainFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ChainFormat::Jsonl),
+            "binary" => Ok(ChainFormat::Binary),
+            _ => Err(format!("unknown chain format {:?}, use jsonl or binary", s)),
+        }
+    }
+}
+
+/// What [`export_chain`] wrote.
+#[derive(Debug)]
+pub struct ExportReport {
+    pub blocks: u64,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+/// What [`import_chain`] added. `error` is the block that stopped it, if
+/// any; the blocks before it stay imported.
+#[derive(Debug)]
+pub struct ImportReport {
+    pub format: ChainFormat,
+    pub imported: u64,
+    pub latest_block_number: Option<u64>,
+    pub latest_block_hash: Hash,
+    pub error: Option<VerifyError>,
+}
+
+/// Writes blocks `from..=to` of `data_dir` to `out` without locking it.
+pub fn export_chain(
+    data_dir: &str,
+    from: u64,
+    to: Option<u64>,
+    format: ChainFormat,
+    out: &mut impl Write,
+) -> Result<ExportReport, DatabaseError> {
+    open_data_dir_read_only(data_dir)?;
+    let (_, blocks, _) = open_data_dir_stores(data_dir, false)?;
+    let stored = blocks.lock().unwrap().iter()?;
+    if format == ChainFormat::Binary {
+        out.write_all(CHAIN_BINARY_MAGIC)?;
+        out.write_all(&CHAIN_BINARY_VERSION.to_be_bytes())?;
+    }
+
+    let mut report = ExportReport {
+        blocks: 0,
+        from: None,
+        to: None,
+    };
+    for block_fs in stored {
+        let block_fs = block_fs?;
+        let number = block_fs.value.header().number();
+        if number < from {
+            continue;
+        }
+        if report.blocks == 0 && number != from {
+            return Err(DatabaseError::BlockNotFound(from));
+        }
+        if to.is_some_and(|to| number > to) {
+            break;
+        }
+        write_block(out, format, &block_fs)?;
+        report.from.get_or_insert(number);
+        report.to = Some(number);
+        report.blocks += 1;
+    }
+    out.flush()?;
+
+    if report.blocks == 0 {
+        return Err(DatabaseError::BlockNotFound(from));
+    }
+    if let Some(to) = to {
+        if report.to != Some(to) {
+            return Err(DatabaseError::BlockNotFound(to));
+        }
+    }
+    Ok(report)
+}
+
+fn write_block(
+    out: &mut impl Write,
+    format: ChainFormat,
+    block_fs: &BlockFS,
+) -> Result<(), DatabaseError> {
+    match format {
+        ChainFormat::Jsonl => {
+            let block_fs_json = serde_json::to_string(block_fs)
+                .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+            writeln!(out, "{}", block_fs_json)?;
+        }
+        ChainFormat::Binary => {
+            let block_json = serde_json::to_vec(&block_fs.value)
+                .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+            out.write_all(&block_fs.key)?;
+            out.write_all(&(block_json.len() as u32).to_be_bytes())?;
+            out.write_all(&block_json)?;
+        }
+    }
+    Ok(())
+}
+
+/// Validates and appends the blocks in `path` to `data_dir`, calling
+/// `progress` with the count and height after each block.
+pub fn import_chain(
+    data_dir: &str,
+    path: &str,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<ImportReport, DatabaseError> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let format = if reader.fill_buf()?.starts_with(CHAIN_BINARY_MAGIC) {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        let version = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        if version > CHAIN_BINARY_VERSION {
+            return Err(DatabaseError::Corrupt(format!(
+                "{} uses binary chain format version {}, this build supports up to {}",
+                path, version, CHAIN_BINARY_VERSION
+            )));
+        }
+        ChainFormat::Binary
+    } else {
+        ChainFormat::Jsonl
+    };
+
+    let mut state = State::new_state_from_disk(data_dir)?;
+    let mut report = ImportReport {
+        format,
+        imported: 0,
+        latest_block_number: None,
+        latest_block_hash: state.latest_block_hash(),
+        error: None,
+    };
+    loop {
+        let block_fs = match read_block(&mut reader, format) {
+            Ok(Some(block_fs)) => block_fs,
+            Ok(None) => break,
+            Err(err) => {
+                report.error = Some(VerifyError {
+                    index: state.next_block_number(),
+                    key: None,
+                    error: err,
+                });
+                break;
+            }
+        };
+        let key = block_fs.key;
+        let number = block_fs.value.header().number();
+        let result = block_fs
+            .verify_key()
+            .and_then(|_| state.add_block(block_fs.value));
+        if let Err(err) = result {
+            report.error = Some(VerifyError {
+                index: number,
+                key: Some(key),
+                error: err,
+            });
+            break;
+        }
+        report.imported += 1;
+        report.latest_block_number = Some(number);
+        report.latest_block_hash = key;
+        progress(report.imported, number);
+    }
+    state.close()?;
+    Ok(report)
+}
+
+fn read_block(
+    reader: &mut impl BufRead,
+    format: ChainFormat,
+) -> Result<Option<BlockFS>, DatabaseError> {
+    match format {
+        ChainFormat::Jsonl => loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            return serde_json::from_str::<BlockFS>(&line)
+                .map(Some)
+                .map_err(|err| DatabaseError::Corrupt(format!("invalid block JSON: {}", err)));
+        },
+        ChainFormat::Binary => {
+            if reader.fill_buf()?.is_empty() {
+                return Ok(None);
+            }
+            let truncated = |err: std::io::Error| match err.kind() {
+                std::io::ErrorKind::UnexpectedEof => {
+                    DatabaseError::Corrupt("truncated block entry".to_string())
+                }
+                _ => err.into(),
+            };
+            let mut key: Hash = [0; 32];
+            reader.read_exact(&mut key).map_err(truncated)?;
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len).map_err(truncated)?;
+            let len = u32::from_be_bytes(len) as usize;
+            if len > MAX_BINARY_BLOCK_BYTES {
+                return Err(DatabaseError::Corrupt(format!(
+                    "block entry of {} bytes exceeds {} bytes",
+                    len, MAX_BINARY_BLOCK_BYTES
+                )));
+            }
+            let mut block_json = vec![0u8; len];
+            reader.read_exact(&mut block_json).map_err(truncated)?;
+            let block = serde_json::from_slice::<Block>(&block_json)
+                .map_err(|err| DatabaseError::Corrupt(format!("invalid block JSON: {}", err)))?;
+            Ok(Some(BlockFS { key, value: block }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        dev_accounts, dev_chain_spec, init_data_dir, Amount, ChainSpec, StorageConfig, Tx,
+        DEV_CHAIN_ID,
+    };
+
+    fn new_data_dir(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("tbb-export-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    fn new_chain(data_dir: &str, spec: &ChainSpec) -> State {
+        let accounts = dev_accounts(2).unwrap();
+        init_data_dir(data_dir, spec, StorageConfig::default()).unwrap();
+        let mut state = State::new_state_from_disk(data_dir).unwrap();
+        state.set_miner(accounts[0].account.clone());
+        for nonce in 1..=3 {
+            let tx = Tx::new(
+                DEV_CHAIN_ID,
+                accounts[0].account.clone(),
+                accounts[1].account.clone(),
+                Amount::new(7),
+                Amount::new(1),
+                nonce,
+                "",
+            );
+            let signature = accounts[0].signing_key().unwrap().sign_tx(&tx).unwrap();
+            state.add_tx(&tx.with_signature(&signature)).unwrap();
+            state.persist().unwrap();
+        }
+        state
+    }
+
+    fn import(spec: &ChainSpec, name: &str, chain: &[u8]) -> (ImportReport, Vec<u64>, State) {
+        let data_dir = new_data_dir(name);
+        init_data_dir(&data_dir, spec, StorageConfig::default()).unwrap();
+        let path = format!("{}/chain.export", data_dir);
+        std::fs::write(&path, chain).unwrap();
+        let mut progress = Vec::new();
+        let report = import_chain(&data_dir, &path, |_, number| progress.push(number)).unwrap();
+        let state = State::new_read_only_state_from_disk(&data_dir).unwrap();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+        (report, progress, state)
+    }
+
+    #[test]
+    fn exported_chain_imports_in_both_formats() {
+        let spec = dev_chain_spec(&dev_accounts(2).unwrap(), 0).unwrap();
+        let data_dir = new_data_dir("source");
+        let source = new_chain(&data_dir, &spec);
+        source.close().unwrap();
+
+        for format in [ChainFormat::Jsonl, ChainFormat::Binary] {
+            let mut chain = Vec::new();
+            let exported = export_chain(&data_dir, 0, None, format, &mut chain).unwrap();
+            assert_eq!((exported.from, exported.to), (Some(0), Some(2)));
+
+            let (report, progress, imported) = import(&spec, &format.to_string(), &chain);
+            assert!(report.error.is_none(), "{:?}", report.error);
+            assert_eq!(report.format, format);
+            assert_eq!(report.imported, 3);
+            assert_eq!(progress, vec![0, 1, 2]);
+            assert_eq!(report.latest_block_hash, source.latest_block_hash());
+            assert_eq!(imported.latest_block_hash(), source.latest_block_hash());
+            assert_eq!(imported.get_balances(), source.get_balances());
+        }
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn import_stops_at_the_first_bad_block() {
+        let spec = dev_chain_spec(&dev_accounts(2).unwrap(), 0).unwrap();
+        let data_dir = new_data_dir("bad-source");
+        new_chain(&data_dir, &spec).close().unwrap();
+
+        let mut chain = Vec::new();
+        export_chain(&data_dir, 0, None, ChainFormat::Jsonl, &mut chain).unwrap();
+        let mut lines: Vec<String> = String::from_utf8(chain)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        assert!(lines[1].contains(r#""value":7,"#));
+        lines[1] = lines[1].replacen(r#""value":7,"#, r#""value":8,"#, 1);
+        let (report, progress, imported) = import(&spec, "tampered", lines.join("\n").as_bytes());
+        let error = report.error.unwrap();
+        assert_eq!(error.index, 1);
+        assert!(matches!(error.error, DatabaseError::KeyMismatch { .. }));
+        assert_eq!(report.imported, 1);
+        assert_eq!(progress, vec![0]);
+        assert_eq!(imported.next_block_number(), 1);
+
+        let mut chain = Vec::new();
+        export_chain(&data_dir, 0, None, ChainFormat::Binary, &mut chain).unwrap();
+        chain.truncate(chain.len() - 1);
+        let (report, _, imported) = import(&spec, "truncated", &chain);
+        let error = report.error.unwrap();
+        assert_eq!(error.index, 2);
+        assert!(matches!(error.error, DatabaseError::Corrupt(_)));
+        assert_eq!(report.imported, 2);
+        assert_eq!(imported.next_block_number(), 2);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::{BufRead, Write};
+
+use super::{
+    file_exists, get_anchor_file_path, get_blocks_db_file_path, get_headers_db_file_path,
+    get_snapshots_dir_path, list_snapshots, write_snapshot, BlockFS, BlockIter, BlockStore,
+    DatabaseError, HeaderFS, Snapshot, SnapshotArchive, StateStore,
+};
+
+#[derive(Debug)]
+pub(crate) struct FlatFileBlockStore {
+    db_path: String,
+    headers_path: String,
+    db_file: Option<std::fs::File>,
+}
+
+impl FlatFileBlockStore {
+    pub(crate) fn open(
+        data_dir: &str,
+        writable: bool,
+    ) -> Result<FlatFileBlockStore, DatabaseError> {
+        let mut store = FlatFileBlockStore {
+            db_path: get_blocks_db_file_path(data_dir)?,
+            headers_path: get_headers_db_file_path(data_dir)?,
+            db_file: None,
+        };
+        if writable {
+            store.reopen()?;
+        } else if !file_exists(&store.db_path) {
+            return Err(DatabaseError::Io(format!(
+                "blocks db {} not found",
+                store.db_path
+            )));
+        }
+        Ok(store)
+    }
+
+    fn reopen(&mut self) -> Result<(), DatabaseError> {
+        let db_file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.db_path)?;
+        self.db_file = Some(db_file);
+        Ok(())
+    }
+
+    fn rewrite(&mut self, lines: &[String]) -> Result<(), DatabaseError> {
+        write_lines(&self.db_path, lines)?;
+        if self.db_file.is_some() {
+            self.reopen()?;
+        }
+        Ok(())
+    }
+
+    fn lines(&self) -> Result<Vec<String>, DatabaseError> {
+        let file = std::fs::File::open(&self.db_path)?;
+        let mut lines = Vec::new();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                lines.push(line);
+            }
+        }
+        Ok(lines)
+    }
+}
+
+impl BlockStore for FlatFileBlockStore {
+    fn append(&mut self, block_fs: &BlockFS) -> Result<(), DatabaseError> {
+        let Some(ref mut db_file) = self.db_file else {
+            return Err(DatabaseError::ReadOnly);
+        };
+        let block_fs_json = serde_json::to_string(block_fs)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        writeln!(db_file, "{}", block_fs_json)?;
+        Ok(())
+    }
+
+    fn iter(&self) -> Result<BlockIter, DatabaseError> {
+        let file = std::fs::File::open(&self.db_path)?;
+        let lines = std::io::BufReader::new(file).lines().enumerate();
+        Ok(Box::new(lines.filter_map(|(index, line)| match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => Some(serde_json::from_str::<BlockFS>(&line).map_err(|err| {
+                DatabaseError::Corrupt(format!("block at line {}: {}", index + 1, err))
+            })),
+            Err(err) => Some(Err(err.into())),
+        })))
+    }
+
+    fn sync(&mut self) -> Result<(), DatabaseError> {
+        if let Some(ref db_file) = self.db_file {
+            db_file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<Option<String>, DatabaseError> {
+        let lines = self.lines()?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|err| DatabaseError::Io(err.to_string()))?
+            .as_secs();
+        let backup_path = format!("{}.{}.bak", self.db_path, timestamp);
+        std::fs::copy(&self.db_path, &backup_path)?;
+        let kept = (len as usize).min(lines.len());
+        self.rewrite(&lines[..kept])?;
+        Ok(Some(backup_path))
+    }
+
+    fn retain_after(&mut self, block_number: u64) -> Result<(), DatabaseError> {
+        let mut kept = Vec::new();
+        for line in self.lines()? {
+            let number = serde_json::from_str::<BlockFS>(&line)
+                .map(|block_fs| block_fs.value.header().number())
+                .unwrap_or(u64::MAX);
+            if number > block_number {
+                kept.push(line);
+            }
+        }
+        self.rewrite(&kept)?;
+        std::fs::File::open(parent_dir(&self.db_path))?.sync_all()?;
+        Ok(())
+    }
+
+    fn add_headers(&mut self, headers: &[HeaderFS]) -> Result<(), DatabaseError> {
+        let Some(first) = headers.first().map(|header_fs| header_fs.header.number()) else {
+            return Ok(());
+        };
+        let mut lines = Vec::new();
+        for header_fs in read_headers(&self.headers_path)? {
+            if header_fs.header.number() < first {
+                lines.push(to_json(&header_fs)?);
+            }
+        }
+        for header_fs in headers {
+            lines.push(to_json(header_fs)?);
+        }
+        write_lines(&self.headers_path, &lines)
+    }
+
+    fn header(&self, block_number: u64) -> Result<Option<HeaderFS>, DatabaseError> {
+        Ok(read_headers(&self.headers_path)?
+            .into_iter()
+            .find(|header_fs| header_fs.header.number() == block_number))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct FlatFileStateStore {
+    snapshot_dir: String,
+    anchor_path: String,
+}
+
+impl FlatFileStateStore {
+    pub(crate) fn open(data_dir: &str) -> Result<FlatFileStateStore, DatabaseError> {
+        Ok(FlatFileStateStore {
+            snapshot_dir: get_snapshots_dir_path(data_dir)?,
+            anchor_path: get_anchor_file_path(data_dir)?,
+        })
+    }
+
+    fn snapshot_path(&self, block_number: u64) -> Result<String, DatabaseError> {
+        list_snapshots(&self.snapshot_dir)?
+            .into_iter()
+            .find(|(number, _)| *number == block_number)
+            .map(|(_, path)| path)
+            .ok_or_else(|| DatabaseError::Corrupt(format!("no snapshot at block {}", block_number)))
+    }
+}
+
+impl StateStore for FlatFileStateStore {
+    fn snapshots(&self) -> Result<Vec<u64>, DatabaseError> {
+        Ok(list_snapshots(&self.snapshot_dir)?
+            .into_iter()
+            .map(|(block_number, _)| block_number)
+            .collect())
+    }
+
+    fn snapshot(&self, block_number: u64) -> Result<Snapshot, DatabaseError> {
+        Snapshot::load(&self.snapshot_path(block_number)?)
+    }
+
+    fn write_snapshot(&mut self, snapshot: &Snapshot, keep: usize) -> Result<(), DatabaseError> {
+        write_snapshot(&self.snapshot_dir, snapshot, keep)?;
+        Ok(())
+    }
+
+    fn remove_snapshots_until(&mut self, block_number: u64) -> Result<(), DatabaseError> {
+        for (number, path) in list_snapshots(&self.snapshot_dir)? {
+            if number <= block_number {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn anchor(&self) -> Result<Option<SnapshotArchive>, DatabaseError> {
+        if !file_exists(&self.anchor_path) {
+            return Ok(None);
+        }
+        SnapshotArchive::load(&self.anchor_path).map(Some)
+    }
+
+    fn set_anchor(&mut self, archive: &SnapshotArchive) -> Result<(), DatabaseError> {
+        archive.write(&self.anchor_path)
+    }
+}
+
+fn read_headers(path: &str) -> Result<Vec<HeaderFS>, DatabaseError> {
+    if !file_exists(path) {
+        return Ok(Vec::new());
+    }
+    let file = std::fs::File::open(path)?;
+    let mut headers = Vec::new();
+    for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let header_fs = serde_json::from_str::<HeaderFS>(&line).map_err(|err| {
+            DatabaseError::Corrupt(format!("{} line {}: {}", path, index + 1, err))
+        })?;
+        headers.push(header_fs);
+    }
+    Ok(headers)
+}
+
+fn to_json(header_fs: &HeaderFS) -> Result<String, DatabaseError> {
+    serde_json::to_string(header_fs).map_err(|err| DatabaseError::Serialization(err.to_string()))
+}
+
+fn parent_dir(path: &str) -> std::path::PathBuf {
+    std::path::Path::new(path)
+        .parent()
+        .map(|parent| parent.to_path_buf())
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+}
+
+fn write_lines(path: &str, lines: &[String]) -> Result<(), DatabaseError> {
+    let tmp_path = format!("{}.tmp", path);
+    let mut tmp_file = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+    for line in lines {
+        writeln!(tmp_file, "{}", line)?;
+    }
+    tmp_file
+        .into_inner()
+        .map_err(|err| DatabaseError::Io(err.to_string()))?
+        .sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -1,49 +1,279 @@
-use super::write_genesis_to_disk;
+use super::{
+    open_stores, write_chain_spec_to_disk, write_genesis_to_disk, BlockFS, ChainSpec, Config,
+    DataDirLock, DatabaseError, Genesis, SnapshotArchive, StorageBackend, StorageConfig,
+};
 
-pub fn init_data_dir_if_not_exists(data_dir: &str) -> std::io::Result<()> {
-    if file_exists(&get_genesis_json_file_path(data_dir).unwrap()) {
-        return Ok(());
-    };
+pub(crate) const DATA_DIR_LAYOUT_VERSION: u32 = 1;
 
-    let Ok(db_dir) = get_database_dir_path(data_dir) else {
-        panic!("Invalid database directory path")
-    };
-    std::fs::create_dir_all(db_dir)?;
-    let Ok(genesis_file_path) = get_genesis_json_file_path(data_dir) else {
-        panic!("Invalid genesis file path")
-    };
-    write_genesis_to_disk(&genesis_file_path);
-    let Ok(blocks_db_file_path) = get_blocks_db_file_path(data_dir) else {
-        panic!("Invalid blocks db file path")
+const LEGACY_STRAY_FILES: [&str; 2] = ["tx.db", "state.json"];
+
+pub(crate) fn init_data_dir_if_not_exists(data_dir: &str) -> Result<(), DatabaseError> {
+    migrate_legacy_data_dir(data_dir)?;
+    let genesis_file_path = get_genesis_json_file_path(data_dir)?;
+    if !file_exists(&genesis_file_path) {
+        std::fs::create_dir_all(get_database_dir_path(data_dir)?)?;
+        write_genesis_to_disk(&genesis_file_path)?;
+        let blocks_db_file_path = get_blocks_db_file_path(data_dir)?;
+        write_empty_blocks_db_file(&blocks_db_file_path)?;
+    }
+    write_layout(data_dir)
+}
+
+/// Creates `data_dir` for the chain described by `spec`. Fails with
+/// [`DatabaseError::AlreadyInitialized`] if it already holds a chain.
+pub fn init_data_dir(
+    data_dir: &str,
+    spec: &ChainSpec,
+    storage: StorageConfig,
+) -> Result<Genesis, DatabaseError> {
+    let genesis = Genesis::from_spec(spec.clone())?;
+    let _lock = DataDirLock::acquire(data_dir)?;
+    create_data_dir(data_dir, spec, storage, None)?;
+    Ok(genesis)
+}
+
+/// Creates `data_dir` starting at the snapshot in `archive` instead of
+/// block 0. The datadir never holds the blocks before the snapshot.
+pub fn init_data_dir_from_snapshot(
+    data_dir: &str,
+    archive: &SnapshotArchive,
+    storage: StorageConfig,
+) -> Result<Genesis, DatabaseError> {
+    archive.verify().map_err(DatabaseError::BadSnapshot)?;
+    if storage.backend == StorageBackend::Memory {
+        return Err(DatabaseError::BadSnapshot(
+            "the memory storage backend cannot keep a snapshot anchor across restarts".to_string(),
+        ));
+    }
+    let spec = &archive.contents.chain_spec;
+    let genesis = Genesis::from_spec(spec.clone())?;
+    let _lock = DataDirLock::acquire(data_dir)?;
+    create_data_dir(data_dir, spec, storage, Some(archive))?;
+    Ok(genesis)
+}
+
+fn create_data_dir(
+    data_dir: &str,
+    spec: &ChainSpec,
+    storage: StorageConfig,
+    anchor: Option<&SnapshotArchive>,
+) -> Result<(), DatabaseError> {
+    migrate_legacy_data_dir(data_dir)?;
+    let genesis_file_path = get_genesis_json_file_path(data_dir)?;
+    let blocks_db_file_path = get_blocks_db_file_path(data_dir)?;
+    let has_blocks = std::fs::metadata(&blocks_db_file_path)
+        .map(|metadata| metadata.len() > 0)
+        .unwrap_or(false);
+    if file_exists(&genesis_file_path)
+        || has_blocks
+        || file_exists(&get_kv_store_file_path(data_dir)?)
+    {
+        return Err(DatabaseError::AlreadyInitialized(data_dir.to_string()));
+    }
+
+    std::fs::create_dir_all(get_database_dir_path(data_dir)?)?;
+    let config_file_path = get_config_file_path(data_dir)?;
+    let mut config = if file_exists(&config_file_path) {
+        Config::load(&config_file_path)?
+    } else {
+        Config::default()
     };
+    if config.storage != storage {
+        config.storage = storage;
+        config.write(&config_file_path)?;
+    }
+    if let Some(archive) = anchor {
+        let (_, store) = open_stores(data_dir, &storage, true)?;
+        store.lock().unwrap().set_anchor(archive)?;
+    }
+    write_chain_spec_to_disk(&genesis_file_path, spec)?;
     write_empty_blocks_db_file(&blocks_db_file_path)?;
+    write_layout(data_dir)
+}
+
+pub(crate) fn open_data_dir_read_only(data_dir: &str) -> Result<(), DatabaseError> {
+    if !dir_exists(data_dir) {
+        return Err(DatabaseError::BadGenesis(format!(
+            "datadir {} not found, run `tbb init --datadir {}`",
+            data_dir, data_dir
+        )));
+    }
+    if needs_migration(data_dir)? {
+        let _lock = DataDirLock::acquire(data_dir)?;
+        migrate_legacy_data_dir(data_dir)?;
+    }
+    check_data_dir_version(data_dir)?;
+    let genesis_file_path = get_genesis_json_file_path(data_dir)?;
+    if !file_exists(&genesis_file_path) {
+        return Err(DatabaseError::BadGenesis(format!(
+            "genesis file {} not found, run `tbb init --datadir {}`",
+            genesis_file_path, data_dir
+        )));
+    }
     Ok(())
 }
 
-pub fn get_database_dir_path(data_dir: &str) -> std::io::Result<String> {
-    let path = std::path::Path::new(data_dir).join("src").join("database");
-    match path.to_str() {
-        Some(path_str) => Ok(path_str.to_string()),
-        None => Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Invalid path",
-        )),
+pub(crate) fn check_data_dir_version(data_dir: &str) -> Result<(), DatabaseError> {
+    let version_file_path = get_version_file_path(data_dir)?;
+    if !file_exists(&version_file_path) {
+        return Ok(());
     }
+    let version = std::fs::read_to_string(&version_file_path)?;
+    let version = version.trim().parse::<u32>().map_err(|err| {
+        DatabaseError::Corrupt(format!(
+            "layout version {:?} in {}: {}",
+            version.trim(),
+            version_file_path,
+            err
+        ))
+    })?;
+    if version > DATA_DIR_LAYOUT_VERSION {
+        return Err(DatabaseError::Corrupt(format!(
+            "{} uses layout version {}, this build supports up to {}",
+            data_dir, version, DATA_DIR_LAYOUT_VERSION
+        )));
+    }
+    Ok(())
 }
 
-pub fn get_genesis_json_file_path(data_dir: &str) -> std::io::Result<String> {
-    let path = std::path::Path::new(data_dir).join("genesis.json");
-    match path.to_str() {
-        Some(path_str) => Ok(path_str.to_string()),
-        None => Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Invalid path",
-        )),
+pub(crate) fn migrate_legacy_data_dir(data_dir: &str) -> Result<(), DatabaseError> {
+    check_data_dir_version(data_dir)?;
+    if !needs_migration(data_dir)? {
+        return Ok(());
     }
+    let root = std::path::Path::new(data_dir);
+    let legacy_genesis = root.join("genesis.json");
+
+    println!(
+        "Migrating legacy datadir {} to layout version {}",
+        data_dir, DATA_DIR_LAYOUT_VERSION
+    );
+    let db_dir = get_database_dir_path(data_dir)?;
+    std::fs::create_dir_all(&db_dir)?;
+    for (from, to) in [
+        (legacy_genesis, get_genesis_json_file_path(data_dir)?),
+        (root.join("block.db"), get_blocks_db_file_path(data_dir)?),
+    ] {
+        if !from.exists() {
+            continue;
+        }
+        if file_exists(&to) {
+            return Err(DatabaseError::Corrupt(format!(
+                "cannot migrate {}: {} already exists",
+                from.display(),
+                to
+            )));
+        }
+        std::fs::rename(&from, &to)?;
+        println!("Migration: moved {} to {}", from.display(), to);
+    }
+    if let Some(legacy_height) = record_legacy_height(data_dir)? {
+        println!(
+            "Migration: blocks up to {} predate chain ids and stay valid without one",
+            legacy_height
+        );
+    }
+
+    let legacy_dir = root.join("legacy");
+    for stray in LEGACY_STRAY_FILES {
+        let from = root.join(stray);
+        if !from.exists() {
+            continue;
+        }
+        std::fs::create_dir_all(&legacy_dir)?;
+        let to = legacy_dir.join(stray);
+        std::fs::rename(&from, &to)?;
+        println!(
+            "Migration: moved unused {} to {}",
+            from.display(),
+            to.display()
+        );
+    }
+
+    for empty_dir in [root.join("src").join("database"), root.join("src")] {
+        if std::fs::remove_dir(&empty_dir).is_ok() {
+            println!("Migration: removed empty {}", empty_dir.display());
+        }
+    }
+
+    write_layout(data_dir)?;
+    println!("Migration of {} complete", data_dir);
+    Ok(())
 }
 
-pub fn get_blocks_db_file_path(data_dir: &str) -> std::io::Result<String> {
-    let path = std::path::Path::new(data_dir).join("block.db");
+fn record_legacy_height(data_dir: &str) -> Result<Option<u64>, DatabaseError> {
+    let blocks_db_file_path = get_blocks_db_file_path(data_dir)?;
+    if !file_exists(&blocks_db_file_path) {
+        return Ok(None);
+    }
+    let mut legacy_height = None;
+    for line in std::fs::read_to_string(&blocks_db_file_path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(block_fs) = serde_json::from_str::<BlockFS>(line) else {
+            break;
+        };
+        let header = block_fs.value.header();
+        if !header.chain_id().is_empty() {
+            break;
+        }
+        legacy_height = Some(header.number());
+    }
+    if let Some(legacy_height) = legacy_height {
+        std::fs::write(
+            get_legacy_height_file_path(data_dir)?,
+            format!("{}\n", legacy_height),
+        )?;
+    }
+    Ok(legacy_height)
+}
+
+pub(crate) fn read_legacy_height(data_dir: &str) -> Result<Option<u64>, DatabaseError> {
+    let legacy_height_file_path = get_legacy_height_file_path(data_dir)?;
+    if !file_exists(&legacy_height_file_path) {
+        return Ok(None);
+    }
+    let legacy_height = std::fs::read_to_string(&legacy_height_file_path)?;
+    legacy_height
+        .trim()
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|err| {
+            DatabaseError::Corrupt(format!(
+                "legacy height {:?} in {}: {}",
+                legacy_height.trim(),
+                legacy_height_file_path,
+                err
+            ))
+        })
+}
+
+fn needs_migration(data_dir: &str) -> Result<bool, DatabaseError> {
+    let legacy_genesis = std::path::Path::new(data_dir).join("genesis.json");
+    Ok(!file_exists(&get_version_file_path(data_dir)?) && legacy_genesis.exists())
+}
+
+fn write_layout(data_dir: &str) -> Result<(), DatabaseError> {
+    std::fs::create_dir_all(get_database_dir_path(data_dir)?)?;
+    std::fs::create_dir_all(get_keystore_dir_path(data_dir)?)?;
+    std::fs::create_dir_all(get_peers_dir_path(data_dir)?)?;
+    let config_file_path = get_config_file_path(data_dir)?;
+    if !file_exists(&config_file_path) {
+        Config::default().write(&config_file_path)?;
+    }
+    let version_file_path = get_version_file_path(data_dir)?;
+    if !file_exists(&version_file_path) {
+        std::fs::write(&version_file_path, format!("{}\n", DATA_DIR_LAYOUT_VERSION))?;
+    }
+    Ok(())
+}
+
+fn data_dir_path(data_dir: &str, parts: &[&str]) -> std::io::Result<String> {
+    let mut path = std::path::Path::new(data_dir).to_path_buf();
+    for part in parts {
+        path.push(part);
+    }
     match path.to_str() {
         Some(path_str) => Ok(path_str.to_string()),
         None => Err(std::io::Error::new(
@@ -53,15 +283,122 @@ pub fn get_blocks_db_file_path(data_dir: &str) -> std::io::Result<String> {
     }
 }
 
-pub fn file_exists(file_path: &str) -> bool {
+pub(crate) fn get_database_dir_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database"])
+}
+
+pub(crate) fn get_snapshots_dir_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "snapshots"])
+}
+
+pub(crate) fn get_keystore_dir_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["keystore"])
+}
+
+pub(crate) fn get_peers_dir_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["peers"])
+}
+
+pub(crate) fn get_config_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["config.json"])
+}
+
+pub(crate) fn get_version_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["VERSION"])
+}
+
+pub(crate) fn get_lock_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["LOCK"])
+}
+
+pub(crate) fn get_genesis_json_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "genesis.json"])
+}
+
+pub(crate) fn get_anchor_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "anchor.json"])
+}
+
+pub(crate) fn get_legacy_height_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "legacy_height"])
+}
+
+pub(crate) fn get_headers_db_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "headers.db"])
+}
+
+pub(crate) fn get_blocks_db_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "block.db"])
+}
+
+pub(crate) fn get_kv_store_file_path(data_dir: &str) -> std::io::Result<String> {
+    data_dir_path(data_dir, &["database", "chain.redb"])
+}
+
+pub(crate) fn file_exists(file_path: &str) -> bool {
     std::path::Path::new(file_path).exists()
 }
 
-pub fn dir_exists(dir_path: &str) -> bool {
+pub(crate) fn dir_exists(dir_path: &str) -> bool {
     std::path::Path::new(dir_path).exists()
 }
 
-pub fn write_empty_blocks_db_file(data_dir: &str) -> std::io::Result<()> {
+pub(crate) fn write_empty_blocks_db_file(data_dir: &str) -> std::io::Result<()> {
     std::fs::write(data_dir, "".as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_data_dir(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("tbb-fs-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn init_refuses_to_overwrite_a_chain() {
+        for backend in [StorageBackend::FlatFile, StorageBackend::Kv] {
+            let data_dir = new_data_dir(&format!("{:?}", backend));
+            let spec = ChainSpec::default_spec().unwrap();
+            init_data_dir(&data_dir, &spec, StorageConfig { backend }).unwrap();
+            let genesis_file_path = get_genesis_json_file_path(&data_dir).unwrap();
+            let genesis_json = std::fs::read(&genesis_file_path).unwrap();
+
+            let mut other = spec.clone();
+            other.chain_id = "other-chain".to_string();
+            for backend in [StorageBackend::FlatFile, StorageBackend::Kv] {
+                assert!(matches!(
+                    init_data_dir(&data_dir, &other, StorageConfig { backend }),
+                    Err(DatabaseError::AlreadyInitialized(_))
+                ));
+            }
+            assert_eq!(std::fs::read(&genesis_file_path).unwrap(), genesis_json);
+            std::fs::remove_dir_all(&data_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn init_refuses_to_overwrite_a_legacy_chain() {
+        let data_dir = new_data_dir("legacy");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let root = std::path::Path::new(&data_dir);
+        std::fs::write(root.join("genesis.json"), include_str!("genesis.json")).unwrap();
+        std::fs::write(root.join("block.db"), "").unwrap();
+
+        let spec = ChainSpec::default_spec().unwrap();
+        assert!(matches!(
+            init_data_dir(&data_dir, &spec, StorageConfig::default()),
+            Err(DatabaseError::AlreadyInitialized(_))
+        ));
+        let genesis_file_path = get_genesis_json_file_path(&data_dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(genesis_file_path).unwrap(),
+            include_str!("genesis.json")
+        );
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -1,63 +1,299 @@
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
     io::{Read, Write},
 };
 
-use super::Account;
+use super::{
+    Account, Amount, BlockLimits, ConsensusConfig, DatabaseError, EngineConfig, FeeRules, Hash,
+    RewardSchedule,
+};
 
 static GENESIS_JSON: &str = r#"
 {
     "genesis_time": "2019-03-18T00:00:00.000000000Z",
     "chain_id": "the-blockchain-bar-ledger",
+    "consensus": {
+        "type": "open",
+        "validators": [],
+        "block_interval_secs": 10
+    },
+    "limits": {
+        "max_block_txs": 1000,
+        "max_block_bytes": 1048576,
+        "max_tx_data_bytes": 256
+    },
+    "fees": {
+        "min_tx_fee": 1,
+        "burn_fees": false
+    },
+    "rewards": {
+        "initial_block_reward": 100,
+        "halving_interval": 210000,
+        "max_supply": 21000000
+    },
     "balances": {
         "andrej": 1000000
     }
 }
 "#;
 
-pub fn write_genesis_to_disk(path: &str) {
-    let mut file = std::fs::File::create(path).unwrap();
-    file.write_all(GENESIS_JSON.as_bytes()).unwrap();
+pub(crate) fn write_genesis_to_disk(path: &str) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(GENESIS_JSON.as_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn write_chain_spec_to_disk(path: &str, spec: &ChainSpec) -> Result<(), DatabaseError> {
+    let spec_json = serde_json::to_string_pretty(spec)
+        .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    writeln!(file, "{}", spec_json)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// The genesis file: chain id, consensus rules and initial balances.
+///
+/// Every node of a chain must start from the same spec. Its hash is the
+/// genesis hash, the parent of block 0.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct ChainSpec {
+    pub genesis_time: String,
+    pub chain_id: String,
+    #[serde(default)]
+    pub consensus: EngineConfig,
+    #[serde(default)]
+    pub limits: BlockLimits,
+    #[serde(default)]
+    pub fees: FeeRules,
+    #[serde(default)]
+    pub rewards: RewardSchedule,
+    pub balances: BTreeMap<Account, Amount>,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct GenesisJson {
-    genesis_time: String,
-    chain_id: String,
-    balances: HashMap<Account, u64>,
+impl ChainSpec {
+    /// The built-in spec used by `tbb init` without `--genesis`.
+    pub fn default_spec() -> Result<ChainSpec, DatabaseError> {
+        serde_json::from_str(GENESIS_JSON).map_err(|err| DatabaseError::BadGenesis(err.to_string()))
+    }
+
+    /// Reads a spec from a JSON file without validating it.
+    pub fn load(path: &str) -> Result<ChainSpec, DatabaseError> {
+        let mut file = std::fs::File::open(path)
+            .map_err(|err| DatabaseError::BadGenesis(format!("{}: {}", path, err)))?;
+        let mut data = String::new();
+        file.read_to_string(&mut data)
+            .map_err(|err| DatabaseError::BadGenesis(format!("{}: {}", path, err)))?;
+        serde_json::from_str(&data)
+            .map_err(|err| DatabaseError::BadGenesis(format!("{}: {}", path, err)))
+    }
+
+    /// The consensus rules set by the spec.
+    pub fn consensus_config(&self) -> ConsensusConfig {
+        ConsensusConfig {
+            engine: self.consensus.clone(),
+            limits: self.limits,
+            fees: self.fees,
+            rewards: self.rewards,
+        }
+    }
+
+    /// Checks the spec and returns the sum of the initial balances.
+    pub fn validate(&self) -> Result<Amount, String> {
+        if self.genesis_time.trim().is_empty() {
+            return Err("genesis_time is empty".to_string());
+        }
+        if self.chain_id.trim().is_empty() {
+            return Err("chain_id is empty".to_string());
+        }
+        let consensus = self.consensus_config();
+        consensus.validate()?;
+        let mut genesis_supply = Amount::ZERO;
+        for (account, balance) in self.balances.iter() {
+            if account.is_empty() {
+                return Err("balances contain an empty account".to_string());
+            }
+            genesis_supply = genesis_supply
+                .checked_add(*balance)
+                .ok_or_else(|| "initial balances overflow the total supply".to_string())?;
+        }
+        if genesis_supply > consensus.rewards.max_supply {
+            return Err(format!(
+                "initial balances of {} TBB exceed rewards.max_supply of {} TBB",
+                genesis_supply, consensus.rewards.max_supply
+            ));
+        }
+        Ok(genesis_supply)
+    }
+
+    /// SHA-256 of the spec's JSON encoding.
+    pub fn hash(&self) -> Result<Hash, DatabaseError> {
+        let spec_json = serde_json::to_string(&self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        let hash = ring::digest::digest(&ring::digest::SHA256, spec_json.as_bytes());
+        let mut result = [0u8; 32];
+        result.copy_from_slice(hash.as_ref());
+        Ok(result)
+    }
 }
 
+/// A validated [`ChainSpec`] with its supply and hash worked out.
 #[derive(Debug)]
 pub struct Genesis {
-    balances: HashMap<Account, u64>,
+    spec: ChainSpec,
+    consensus: ConsensusConfig,
+    supply: Amount,
+    hash: Hash,
 }
 
 impl Genesis {
-    pub fn load_genesis(path: &str) -> Genesis {
-        let Ok(mut file) = std::fs::File::open(path) else {
-            panic!("Genesis file not found");
-        };
-        let mut data = String::new();
-        let Ok(_) = file.read_to_string(&mut data) else {
-            panic!("Error reading genesis file");
-        };
-        let json: GenesisJson = serde_json::from_str(&data).unwrap();
-        let mut balances = HashMap::new();
-        for (account, balance) in json.balances.iter() {
-            balances.insert(account.clone(), balance.to_owned());
-        }
-        Genesis { balances }
+    /// Loads and validates the spec at `path`.
+    pub fn load_genesis(path: &str) -> Result<Genesis, DatabaseError> {
+        let spec = ChainSpec::load(path)?;
+        Genesis::from_spec(spec).map_err(|err| match err {
+            DatabaseError::BadGenesis(err) => {
+                DatabaseError::BadGenesis(format!("{}: {}", path, err))
+            }
+            err => err,
+        })
+    }
+
+    /// Validates `spec`.
+    pub fn from_spec(spec: ChainSpec) -> Result<Genesis, DatabaseError> {
+        let supply = spec.validate().map_err(DatabaseError::BadGenesis)?;
+        let hash = spec.hash()?;
+        let consensus = spec.consensus_config();
+        Ok(Genesis {
+            spec,
+            consensus,
+            supply,
+            hash,
+        })
     }
 
-    pub fn get_balance(&self, account: &Account) -> u64 {
-        *self.balances.get(account).unwrap_or(&0)
+    /// The chain id blocks and txs must carry.
+    pub fn chain_id(&self) -> &str {
+        &self.spec.chain_id
+    }
+    /// Initial balance of `account`, zero if it has none.
+    pub fn get_balance(&self, account: &Account) -> Amount {
+        *self.spec.balances.get(account).unwrap_or(&Amount::ZERO)
+    }
+
+    /// Sets the initial balance of `account`. Fails, leaving the genesis
+    /// unchanged, if the new balances exceed `rewards.max_supply`. Changes the
+    /// genesis hash.
+    pub fn set_balance(&mut self, account: Account, balance: Amount) -> Result<(), DatabaseError> {
+        let mut spec = self.spec.clone();
+        spec.balances.insert(account, balance);
+        *self = Genesis::from_spec(spec)?;
+        Ok(())
     }
 
-    pub fn set_balance(&mut self, account: Account, balance: u64) {
-        self.balances.insert(account, balance);
+    /// Initial balances by account.
+    pub fn get_balances(&self) -> &BTreeMap<Account, Amount> {
+        &self.spec.balances
+    }
+    /// The chain's consensus rules.
+    pub fn consensus(&self) -> &ConsensusConfig {
+        &self.consensus
+    }
+    /// Sum of the initial balances.
+    pub fn supply(&self) -> Amount {
+        self.supply
     }
+    /// The genesis hash, see [`ChainSpec`].
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::ConsensusEngine;
+    use data_encoding::HEXLOWER;
+
+    type BreakSpec = fn(&mut ChainSpec);
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        let cases: [(BreakSpec, &str); 7] = [
+            (|spec| spec.chain_id = " ".to_string(), "chain_id is empty"),
+            (
+                |spec| spec.consensus.block_interval_secs = 0,
+                "block_interval_secs must be greater than 0",
+            ),
+            (
+                |spec| spec.consensus.engine = ConsensusEngine::ProofOfAuthority,
+                "proof_of_authority needs at least one validator",
+            ),
+            (
+                |spec| spec.limits.max_tx_data_bytes = spec.limits.max_block_bytes,
+                "must be below limits.max_block_bytes",
+            ),
+            (
+                |spec| spec.rewards.max_supply = Amount::new(99),
+                "rewards.initial_block_reward 100 exceeds",
+            ),
+            (
+                |spec| {
+                    spec.balances.insert(String::new(), Amount::new(1));
+                },
+                "balances contain an empty account",
+            ),
+            (
+                |spec| {
+                    spec.balances
+                        .insert("babayaga".to_string(), Amount::new(21_000_000));
+                },
+                "exceed rewards.max_supply",
+            ),
+        ];
+        for (break_spec, expected) in cases {
+            let mut spec = ChainSpec::default_spec().unwrap();
+            break_spec(&mut spec);
+            let err = spec.validate().unwrap_err();
+            assert!(
+                err.contains(expected),
+                "{:?} does not contain {:?}",
+                err,
+                expected
+            );
+            assert!(matches!(
+                Genesis::from_spec(spec),
+                Err(DatabaseError::BadGenesis(_))
+            ));
+        }
+        let spec = ChainSpec::default_spec().unwrap();
+        assert_eq!(spec.validate(), Ok(Amount::new(1_000_000)));
+    }
+
+    #[test]
+    fn genesis_hash_is_stable() {
+        let spec = ChainSpec::default_spec().unwrap();
+        assert_eq!(
+            HEXLOWER.encode(&spec.hash().unwrap()),
+            "d85afa392ce8de050f49a55dff183536e9a26187c3f3a48dfd573d5fab9cd67e"
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("tbb-genesis-test-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        write_chain_spec_to_disk(path, &spec).unwrap();
+        let reloaded = Genesis::load_genesis(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(reloaded.hash(), spec.hash().unwrap());
 
-    pub fn get_balances(&self) -> &HashMap<Account, u64> {
-        &self.balances
+        let mut funded = Genesis::from_spec(spec).unwrap();
+        let hash = funded.hash();
+        funded
+            .set_balance("babayaga".to_string(), Amount::new(1))
+            .unwrap();
+        assert_ne!(funded.hash(), hash);
     }
 }
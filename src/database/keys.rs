@@ -0,0 +1,99 @@
+use data_encoding::HEXLOWER;
+use ring::signature::{
+    Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519, ED25519_PUBLIC_KEY_LEN,
+};
+
+use super::{Account, DatabaseError, Hash, Tx};
+
+pub(crate) const SECRET_KEY_BYTES: usize = 32;
+
+/// An ed25519 key. Its account is the hex public key.
+#[derive(Debug)]
+pub struct SigningKey {
+    key_pair: Ed25519KeyPair,
+    account: Account,
+}
+
+impl SigningKey {
+    /// The key for a 32-byte secret.
+    pub fn from_seed(seed: &[u8]) -> Result<SigningKey, DatabaseError> {
+        if seed.len() != SECRET_KEY_BYTES {
+            return Err(DatabaseError::BadSignature(format!(
+                "secret key must be {} bytes, got {}",
+                SECRET_KEY_BYTES,
+                seed.len()
+            )));
+        }
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|err| DatabaseError::BadSignature(format!("secret key: {}", err)))?;
+        let account = HEXLOWER.encode(key_pair.public_key().as_ref());
+        Ok(SigningKey { key_pair, account })
+    }
+
+    /// The key for a hex-encoded 32-byte secret.
+    pub fn from_hex(secret_key: &str) -> Result<SigningKey, DatabaseError> {
+        let seed = HEXLOWER
+            .decode(secret_key.trim().to_lowercase().as_bytes())
+            .map_err(|err| DatabaseError::BadSignature(format!("secret key: {}", err)))?;
+        SigningKey::from_seed(&seed)
+    }
+
+    /// The account this key signs for.
+    pub fn account(&self) -> &Account {
+        &self.account
+    }
+
+    /// Hex signature of `hash`.
+    pub fn sign_hash(&self, hash: &Hash) -> String {
+        HEXLOWER.encode(self.key_pair.sign(hash).as_ref())
+    }
+
+    /// Hex signature of [`Tx::hash`]. Fails if the tx is not from this key's
+    /// account.
+    pub fn sign_tx(&self, tx: &Tx) -> Result<String, DatabaseError> {
+        if *tx.from() != self.account {
+            return Err(DatabaseError::BadSignature(format!(
+                "tx is from {}, the key belongs to {}",
+                tx.from(),
+                self.account
+            )));
+        }
+        Ok(self.sign_hash(&tx.hash()?))
+    }
+}
+
+pub(crate) fn is_key_account(account: &Account) -> bool {
+    HEXLOWER
+        .decode(account.as_bytes())
+        .is_ok_and(|public_key| public_key.len() == ED25519_PUBLIC_KEY_LEN)
+}
+
+pub(crate) fn check_tx_signature(tx: &Tx) -> Result<(), DatabaseError> {
+    if tx.is_coinbase() {
+        return Ok(());
+    }
+    match tx.signature() {
+        Some(signature) => verify_tx_signature(tx, signature),
+        None if is_key_account(tx.from()) => Err(DatabaseError::BadSignature(format!(
+            "tx from {} is not signed",
+            tx.from()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `signature` is a signature of [`Tx::hash`] by the tx's
+/// sender.
+pub fn verify_tx_signature(tx: &Tx, signature: &str) -> Result<(), DatabaseError> {
+    let public_key = HEXLOWER.decode(tx.from().as_bytes()).map_err(|_| {
+        DatabaseError::BadSignature(format!("sender {} is not a hex public key", tx.from()))
+    })?;
+    let signature = HEXLOWER
+        .decode(signature.trim().to_lowercase().as_bytes())
+        .map_err(|err| DatabaseError::BadSignature(format!("signature: {}", err)))?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(&tx.hash()?, &signature)
+        .map_err(|_| {
+            DatabaseError::BadSignature(format!("signature does not match sender {}", tx.from()))
+        })
+}
@@ -0,0 +1,279 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use redb::{ReadableTable, ReadableTableMetadata, TableDefinition};
+
+use super::{
+    file_exists, get_kv_store_file_path, BlockFS, BlockIter, BlockStore, DataDirLock,
+    DatabaseError, HeaderFS, Snapshot, SnapshotArchive, StateStore,
+};
+
+const BLOCKS: TableDefinition<u64, &[u8]> = TableDefinition::new("blocks");
+const HEADERS: TableDefinition<u64, &[u8]> = TableDefinition::new("headers");
+const SNAPSHOTS: TableDefinition<u64, &[u8]> = TableDefinition::new("snapshots");
+const META: TableDefinition<&str, &[u8]> = TableDefinition::new("meta");
+
+const META_ANCHOR: &str = "anchor";
+const ITER_BATCH_SIZE: usize = 256;
+
+#[derive(Debug, Clone)]
+pub(crate) struct KvStore {
+    db: Arc<redb::Database>,
+    writable: bool,
+}
+
+impl KvStore {
+    pub(crate) fn open(data_dir: &str, writable: bool) -> Result<KvStore, DatabaseError> {
+        let path = get_kv_store_file_path(data_dir)?;
+        if !writable && !file_exists(&path) {
+            return Err(DatabaseError::Io(format!("kv store {} not found", path)));
+        }
+        let db = match writable {
+            true => redb::Database::create(&path),
+            false => redb::Database::open(&path),
+        };
+        // redb holds an exclusive lock on the file for as long as it is open,
+        // so readers cannot share it with a running node. A writer that hits
+        // the lock is racing a reader, which leaves no pid behind.
+        let db = db.map_err(|err| match err {
+            redb::DatabaseError::DatabaseAlreadyOpen => DatabaseError::DataDirLocked {
+                data_dir: data_dir.to_string(),
+                pid: match writable {
+                    true => None,
+                    false => DataDirLock::holder(data_dir),
+                },
+            },
+            err => kv_error(err),
+        })?;
+        if writable {
+            let txn = db.begin_write().map_err(kv_error)?;
+            txn.open_table(BLOCKS).map_err(kv_error)?;
+            txn.open_table(HEADERS).map_err(kv_error)?;
+            txn.open_table(SNAPSHOTS).map_err(kv_error)?;
+            txn.open_table(META).map_err(kv_error)?;
+            txn.commit().map_err(kv_error)?;
+        }
+        Ok(KvStore {
+            db: Arc::new(db),
+            writable,
+        })
+    }
+
+    fn write(
+        &self,
+        f: impl FnOnce(&redb::WriteTransaction) -> Result<(), DatabaseError>,
+    ) -> Result<(), DatabaseError> {
+        if !self.writable {
+            return Err(DatabaseError::ReadOnly);
+        }
+        let txn = self.db.begin_write().map_err(kv_error)?;
+        f(&txn)?;
+        txn.commit().map_err(kv_error)
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        table: TableDefinition<u64, &[u8]>,
+        key: u64,
+    ) -> Result<Option<T>, DatabaseError> {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let table = txn.open_table(table).map_err(kv_error)?;
+        match table.get(key).map_err(kv_error)? {
+            Some(value) => decode(value.value()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn remove_range(
+        &self,
+        table: TableDefinition<u64, &[u8]>,
+        range: impl std::ops::RangeBounds<u64>,
+    ) -> Result<(), DatabaseError> {
+        self.write(|txn| {
+            let mut table = txn.open_table(table).map_err(kv_error)?;
+            table.retain_in(range, |_, _| false).map_err(kv_error)
+        })
+    }
+}
+
+impl BlockStore for KvStore {
+    fn append(&mut self, block_fs: &BlockFS) -> Result<(), DatabaseError> {
+        let value = encode(block_fs)?;
+        self.write(|txn| {
+            let mut table = txn.open_table(BLOCKS).map_err(kv_error)?;
+            table
+                .insert(block_fs.value.header().number(), value.as_slice())
+                .map_err(kv_error)?;
+            Ok(())
+        })
+    }
+
+    fn iter(&self) -> Result<BlockIter, DatabaseError> {
+        Ok(Box::new(KvBlockIter {
+            db: self.db.clone(),
+            next: Some(0),
+            batch: VecDeque::new(),
+        }))
+    }
+
+    fn sync(&mut self) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<Option<String>, DatabaseError> {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let table = txn.open_table(BLOCKS).map_err(kv_error)?;
+        if table.len().map_err(kv_error)? <= len {
+            return Ok(None);
+        }
+        let first_dropped = match table.iter().map_err(kv_error)?.nth(len as usize) {
+            Some(entry) => entry.map_err(kv_error)?.0.value(),
+            None => return Ok(None),
+        };
+        drop(table);
+        drop(txn);
+        self.remove_range(BLOCKS, first_dropped..)?;
+        Ok(None)
+    }
+
+    fn retain_after(&mut self, block_number: u64) -> Result<(), DatabaseError> {
+        self.remove_range(BLOCKS, ..=block_number)
+    }
+
+    fn add_headers(&mut self, headers: &[HeaderFS]) -> Result<(), DatabaseError> {
+        let mut values = Vec::with_capacity(headers.len());
+        for header_fs in headers {
+            values.push((header_fs.header.number(), encode(header_fs)?));
+        }
+        self.write(|txn| {
+            let mut table = txn.open_table(HEADERS).map_err(kv_error)?;
+            for (block_number, value) in values.iter() {
+                table
+                    .insert(*block_number, value.as_slice())
+                    .map_err(kv_error)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn header(&self, block_number: u64) -> Result<Option<HeaderFS>, DatabaseError> {
+        self.get(HEADERS, block_number)
+    }
+
+    fn get(&self, block_number: u64) -> Result<Option<BlockFS>, DatabaseError> {
+        KvStore::get(self, BLOCKS, block_number)
+    }
+}
+
+impl StateStore for KvStore {
+    fn snapshots(&self) -> Result<Vec<u64>, DatabaseError> {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let table = txn.open_table(SNAPSHOTS).map_err(kv_error)?;
+        let mut block_numbers = Vec::new();
+        for entry in table.iter().map_err(kv_error)?.rev() {
+            block_numbers.push(entry.map_err(kv_error)?.0.value());
+        }
+        Ok(block_numbers)
+    }
+
+    fn snapshot(&self, block_number: u64) -> Result<Snapshot, DatabaseError> {
+        self.get(SNAPSHOTS, block_number)?
+            .ok_or_else(|| DatabaseError::Corrupt(format!("no snapshot at block {}", block_number)))
+    }
+
+    fn write_snapshot(&mut self, snapshot: &Snapshot, keep: usize) -> Result<(), DatabaseError> {
+        let value = encode(snapshot)?;
+        self.write(|txn| {
+            let mut table = txn.open_table(SNAPSHOTS).map_err(kv_error)?;
+            table
+                .insert(snapshot.block_number, value.as_slice())
+                .map_err(kv_error)?;
+            while table.len().map_err(kv_error)? > keep.max(1) as u64 {
+                table.pop_first().map_err(kv_error)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove_snapshots_until(&mut self, block_number: u64) -> Result<(), DatabaseError> {
+        self.remove_range(SNAPSHOTS, ..=block_number)
+    }
+
+    fn anchor(&self) -> Result<Option<SnapshotArchive>, DatabaseError> {
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let table = txn.open_table(META).map_err(kv_error)?;
+        let Some(value) = table.get(META_ANCHOR).map_err(kv_error)? else {
+            return Ok(None);
+        };
+        let archive = decode::<SnapshotArchive>(value.value())?;
+        archive.verify().map_err(DatabaseError::BadSnapshot)?;
+        Ok(Some(archive))
+    }
+
+    fn set_anchor(&mut self, archive: &SnapshotArchive) -> Result<(), DatabaseError> {
+        let value = encode(archive)?;
+        self.write(|txn| {
+            let mut table = txn.open_table(META).map_err(kv_error)?;
+            table
+                .insert(META_ANCHOR, value.as_slice())
+                .map_err(kv_error)?;
+            Ok(())
+        })
+    }
+}
+
+struct KvBlockIter {
+    db: Arc<redb::Database>,
+    next: Option<u64>,
+    batch: VecDeque<Result<BlockFS, DatabaseError>>,
+}
+
+impl KvBlockIter {
+    fn fill(&mut self) -> Result<(), DatabaseError> {
+        let Some(start) = self.next.take() else {
+            return Ok(());
+        };
+        let txn = self.db.begin_read().map_err(kv_error)?;
+        let table = txn.open_table(BLOCKS).map_err(kv_error)?;
+        for entry in table
+            .range(start..)
+            .map_err(kv_error)?
+            .take(ITER_BATCH_SIZE)
+        {
+            let (key, value) = entry.map_err(kv_error)?;
+            self.batch.push_back(decode(value.value()));
+            self.next = key.value().checked_add(1);
+        }
+        if self.batch.len() < ITER_BATCH_SIZE {
+            self.next = None;
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for KvBlockIter {
+    type Item = Result<BlockFS, DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_empty() {
+            if let Err(err) = self.fill() {
+                self.next = None;
+                return Some(Err(err));
+            }
+        }
+        self.batch.pop_front()
+    }
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>, DatabaseError> {
+    serde_json::to_vec(value).map_err(|err| DatabaseError::Serialization(err.to_string()))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: &[u8]) -> Result<T, DatabaseError> {
+    serde_json::from_slice(value)
+        .map_err(|err| DatabaseError::Corrupt(format!("kv store: {}", err)))
+}
+
+fn kv_error(err: impl Into<redb::Error>) -> DatabaseError {
+    DatabaseError::Io(format!("kv store: {}", err.into()))
+}
@@ -0,0 +1,50 @@
+use std::io::{Read, Seek, Write};
+
+use super::{get_lock_file_path, DatabaseError};
+
+#[derive(Debug)]
+pub(crate) struct DataDirLock {
+    file: std::fs::File,
+}
+
+impl DataDirLock {
+    pub(crate) fn acquire(data_dir: &str) -> Result<DataDirLock, DatabaseError> {
+        std::fs::create_dir_all(data_dir)?;
+        let path = get_lock_file_path(data_dir)?;
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => {
+                let mut pid = String::new();
+                file.read_to_string(&mut pid)?;
+                return Err(DatabaseError::DataDirLocked {
+                    data_dir: data_dir.to_string(),
+                    pid: pid.trim().parse().ok(),
+                });
+            }
+            Err(std::fs::TryLockError::Error(err)) => return Err(err.into()),
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(DataDirLock { file })
+    }
+
+    pub(crate) fn holder(data_dir: &str) -> Option<u32> {
+        let path = get_lock_file_path(data_dir).ok()?;
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use super::{
+    BlockFS, BlockIter, BlockStore, DatabaseError, HeaderFS, Snapshot, SnapshotArchive, StateStore,
+};
+
+#[derive(Debug, Default)]
+pub(crate) struct MemoryBlockStore {
+    blocks: Vec<BlockFS>,
+    headers: BTreeMap<u64, HeaderFS>,
+}
+
+impl BlockStore for MemoryBlockStore {
+    fn append(&mut self, block_fs: &BlockFS) -> Result<(), DatabaseError> {
+        self.blocks.push(block_fs.clone());
+        Ok(())
+    }
+
+    fn iter(&self) -> Result<BlockIter, DatabaseError> {
+        Ok(Box::new(self.blocks.clone().into_iter().map(Ok)))
+    }
+
+    fn sync(&mut self) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<Option<String>, DatabaseError> {
+        self.blocks.truncate(len as usize);
+        Ok(None)
+    }
+
+    fn retain_after(&mut self, block_number: u64) -> Result<(), DatabaseError> {
+        self.blocks
+            .retain(|block_fs| block_fs.value.header().number() > block_number);
+        Ok(())
+    }
+
+    fn add_headers(&mut self, headers: &[HeaderFS]) -> Result<(), DatabaseError> {
+        for header_fs in headers {
+            self.headers
+                .insert(header_fs.header.number(), header_fs.clone());
+        }
+        Ok(())
+    }
+
+    fn header(&self, block_number: u64) -> Result<Option<HeaderFS>, DatabaseError> {
+        Ok(self.headers.get(&block_number).cloned())
+    }
+
+    fn get(&self, block_number: u64) -> Result<Option<BlockFS>, DatabaseError> {
+        Ok(self
+            .blocks
+            .iter()
+            .find(|block_fs| block_fs.value.header().number() == block_number)
+            .cloned())
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MemoryStateStore {
+    snapshots: BTreeMap<u64, Snapshot>,
+    anchor: Option<SnapshotArchive>,
+}
+
+impl StateStore for MemoryStateStore {
+    fn snapshots(&self) -> Result<Vec<u64>, DatabaseError> {
+        Ok(self.snapshots.keys().rev().copied().collect())
+    }
+
+    fn snapshot(&self, block_number: u64) -> Result<Snapshot, DatabaseError> {
+        self.snapshots
+            .get(&block_number)
+            .cloned()
+            .ok_or_else(|| DatabaseError::Corrupt(format!("no snapshot at block {}", block_number)))
+    }
+
+    fn write_snapshot(&mut self, snapshot: &Snapshot, keep: usize) -> Result<(), DatabaseError> {
+        self.snapshots
+            .insert(snapshot.block_number, snapshot.clone());
+        while self.snapshots.len() > keep.max(1) {
+            self.snapshots.pop_first();
+        }
+        Ok(())
+    }
+
+    fn remove_snapshots_until(&mut self, block_number: u64) -> Result<(), DatabaseError> {
+        self.snapshots.retain(|number, _| *number > block_number);
+        Ok(())
+    }
+
+    fn anchor(&self) -> Result<Option<SnapshotArchive>, DatabaseError> {
+        Ok(self.anchor.clone())
+    }
+
+    fn set_anchor(&mut self, archive: &SnapshotArchive) -> Result<(), DatabaseError> {
+        self.anchor = Some(archive.clone());
+        Ok(())
+    }
+}
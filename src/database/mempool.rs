@@ -0,0 +1,455 @@
+use std::collections::{BinaryHeap, HashMap};
+
+use super::{Account, Amount, DatabaseError, Hash, Tx};
+
+pub(crate) const DEFAULT_MEMPOOL_MAX_TXS: usize = 5000;
+pub(crate) const DEFAULT_MEMPOOL_MAX_BYTES: usize = 5 * 1024 * 1024;
+pub(crate) const DEFAULT_MEMPOOL_TTL_SECS: u64 = 3 * 60 * 60;
+
+/// The `mempool` section of the datadir config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolConfig {
+    pub max_txs: usize,
+    pub max_bytes: usize,
+    pub ttl_secs: u64,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            max_txs: DEFAULT_MEMPOOL_MAX_TXS,
+            max_bytes: DEFAULT_MEMPOOL_MAX_BYTES,
+            ttl_secs: DEFAULT_MEMPOOL_TTL_SECS,
+        }
+    }
+}
+
+/// A tx waiting in the mempool since `added_at`, in Unix seconds.
+#[derive(Debug, Clone)]
+pub struct PendingTx {
+    pub tx: Tx,
+    pub hash: Hash,
+    pub size: usize,
+    pub added_at: u64,
+}
+
+/// Number of pending txs with a fee in `min_fee..=max_fee`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FeeBucket {
+    pub min_fee: Amount,
+    pub max_fee: Amount,
+    pub count: usize,
+}
+
+/// Txs accepted by [`State::add_tx`](crate::State::add_tx) and not sealed yet,
+/// at most one per sender and nonce.
+#[derive(Debug, Clone, Default)]
+pub struct Mempool {
+    config: MempoolConfig,
+    txs: HashMap<(Account, u64), PendingTx>,
+    bytes: usize,
+}
+
+impl Mempool {
+    pub(crate) fn new(config: MempoolConfig) -> Self {
+        Mempool {
+            config,
+            ..Mempool::default()
+        }
+    }
+    /// Number of pending txs.
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+    /// True if nothing is pending.
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+    /// Total size of the pending txs' JSON encodings.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+    /// The pending tx with hash `hash`.
+    pub fn get_by_hash(&self, hash: &Hash) -> Option<&PendingTx> {
+        self.txs.values().find(|pending| pending.hash == *hash)
+    }
+    /// Pending txs, highest fee first.
+    pub fn txs(&self) -> Vec<&PendingTx> {
+        let mut txs: Vec<&PendingTx> = self.txs.values().collect();
+        txs.sort_by(|a, b| {
+            b.tx.fee()
+                .cmp(&a.tx.fee())
+                .then(a.added_at.cmp(&b.added_at))
+                .then(a.hash.cmp(&b.hash))
+        });
+        txs
+    }
+    /// Pending txs counted by fee in power-of-two buckets, highest first.
+    pub fn fee_histogram(&self) -> Vec<FeeBucket> {
+        let mut buckets: Vec<FeeBucket> = Vec::new();
+        for pending in self.txs() {
+            let fee = pending.tx.fee().value();
+            let (min_fee, max_fee) = match fee {
+                0 => (0, 0),
+                fee => {
+                    let min_fee = 1u128 << fee.ilog2();
+                    (min_fee, min_fee.saturating_sub(1).saturating_add(min_fee))
+                }
+            };
+            match buckets.last_mut() {
+                Some(bucket) if bucket.min_fee.value() == min_fee => bucket.count += 1,
+                _ => buckets.push(FeeBucket {
+                    min_fee: Amount::new(min_fee),
+                    max_fee: Amount::new(max_fee),
+                    count: 1,
+                }),
+            }
+        }
+        buckets
+    }
+    /// Pending txs from `from` in nonce order.
+    pub fn pending_for(&self, from: &Account) -> Vec<&PendingTx> {
+        let mut pending: Vec<&PendingTx> = self
+            .txs
+            .values()
+            .filter(|pending| pending.tx.from() == from)
+            .collect();
+        pending.sort_by_key(|pending| pending.tx.nonce());
+        pending
+    }
+
+    pub(crate) fn add(&mut self, tx: Tx, now: u64) -> Result<Option<Tx>, DatabaseError> {
+        let hash = tx.hash()?;
+        let size = serde_json::to_vec(&tx)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?
+            .len();
+        let key = (tx.from().clone(), tx.nonce());
+        if let Some(existing) = self.txs.get(&key) {
+            if existing.hash == hash {
+                return Err(DatabaseError::DuplicateTx);
+            }
+            if tx.fee() <= existing.tx.fee() {
+                return Err(DatabaseError::ReplacementUnderpriced {
+                    fee: tx.fee(),
+                    current: existing.tx.fee(),
+                });
+            }
+        }
+
+        let replaced = self.remove(&key);
+        self.insert(PendingTx {
+            tx,
+            hash,
+            size,
+            added_at: now,
+        });
+
+        let mut evicted = Vec::new();
+        while self.txs.len() > self.config.max_txs || self.bytes > self.config.max_bytes {
+            let Some(evict_key) = self.cheapest_evictable() else {
+                break;
+            };
+            let Some(pending) = self.remove(&evict_key) else {
+                break;
+            };
+            if evict_key == key {
+                for pending in evicted.into_iter().chain(replaced) {
+                    self.insert(pending);
+                }
+                return Err(DatabaseError::MempoolFull);
+            }
+            evicted.push(pending);
+        }
+        Ok(replaced.map(|pending| pending.tx))
+    }
+
+    pub(crate) fn remove_by_hash(&mut self, hash: &Hash) -> Option<PendingTx> {
+        let key = self
+            .txs
+            .iter()
+            .find(|(_, pending)| pending.hash == *hash)
+            .map(|(key, _)| key.clone())?;
+        let removed = self.remove(&key);
+        self.remove_descendants(&key);
+        removed
+    }
+
+    pub(crate) fn remove_included(&mut self, txs: &[Tx]) {
+        for tx in txs {
+            let key = (tx.from().clone(), tx.nonce());
+            if self.txs.get(&key).is_some_and(|pending| pending.tx == *tx) {
+                self.remove(&key);
+            }
+        }
+    }
+
+    pub(crate) fn expire(&mut self, now: u64) -> Vec<Tx> {
+        let expired: Vec<(Account, u64)> = self
+            .txs
+            .iter()
+            .filter(|(_, pending)| now.saturating_sub(pending.added_at) > self.config.ttl_secs)
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut removed = Vec::new();
+        for key in expired {
+            if let Some(pending) = self.remove(&key) {
+                removed.push(pending.tx);
+            }
+            removed.extend(self.remove_descendants(&key));
+        }
+        removed
+    }
+
+    pub(crate) fn prune<F>(&mut self, next_nonce: F)
+    where
+        F: Fn(&Account) -> u64,
+    {
+        let stale: Vec<(Account, u64)> = self
+            .txs
+            .keys()
+            .filter(|(from, nonce)| *nonce < next_nonce(from))
+            .cloned()
+            .collect();
+        for key in stale {
+            self.remove(&key);
+        }
+    }
+
+    pub(crate) fn select<F>(&self, next_nonce: F) -> Vec<Tx>
+    where
+        F: Fn(&Account) -> u64,
+    {
+        let mut by_sender: HashMap<&Account, Vec<&PendingTx>> = HashMap::new();
+        let mut queues: Vec<Vec<&PendingTx>> = Vec::new();
+        for pending in self.txs.values() {
+            by_sender
+                .entry(pending.tx.from())
+                .or_default()
+                .push(pending);
+        }
+
+        for (from, mut pending) in by_sender {
+            pending.sort_by_key(|pending| pending.tx.nonce());
+            let mut ready = Vec::new();
+            for (expected, pending) in (next_nonce(from)..).zip(pending) {
+                if pending.tx.nonce() != expected {
+                    break;
+                }
+                ready.push(pending);
+            }
+            ready.reverse();
+            if !ready.is_empty() {
+                queues.push(ready);
+            }
+        }
+
+        let mut heap = BinaryHeap::new();
+        for (index, queue) in queues.iter().enumerate() {
+            let head = queue[queue.len() - 1];
+            heap.push((head.tx.fee(), std::cmp::Reverse(head.added_at), index));
+        }
+        let mut selected = Vec::new();
+        while let Some((_, _, index)) = heap.pop() {
+            let Some(pending) = queues[index].pop() else {
+                continue;
+            };
+            selected.push(pending.tx.clone());
+            if let Some(next) = queues[index].last() {
+                heap.push((next.tx.fee(), std::cmp::Reverse(next.added_at), index));
+            }
+        }
+        selected
+    }
+
+    fn insert(&mut self, pending: PendingTx) {
+        self.bytes += pending.size;
+        self.txs
+            .insert((pending.tx.from().clone(), pending.tx.nonce()), pending);
+    }
+
+    fn remove(&mut self, key: &(Account, u64)) -> Option<PendingTx> {
+        let removed = self.txs.remove(key)?;
+        self.bytes -= removed.size;
+        Some(removed)
+    }
+
+    fn remove_descendants(&mut self, key: &(Account, u64)) -> Vec<Tx> {
+        let descendants: Vec<(Account, u64)> = self
+            .txs
+            .keys()
+            .filter(|(from, nonce)| *from == key.0 && *nonce > key.1)
+            .cloned()
+            .collect();
+        descendants
+            .into_iter()
+            .filter_map(|key| self.remove(&key).map(|pending| pending.tx))
+            .collect()
+    }
+
+    fn cheapest_evictable(&self) -> Option<(Account, u64)> {
+        let mut last_by_sender: HashMap<&Account, &PendingTx> = HashMap::new();
+        for pending in self.txs.values() {
+            let last = last_by_sender.entry(pending.tx.from()).or_insert(pending);
+            if pending.tx.nonce() > last.tx.nonce() {
+                *last = pending;
+            }
+        }
+        last_by_sender
+            .into_values()
+            .min_by_key(|pending| (pending.tx.fee(), std::cmp::Reverse(pending.added_at)))
+            .map(|pending| (pending.tx.from().clone(), pending.tx.nonce()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transfer(from: &str, fee: u64, nonce: u64, data: &str) -> Tx {
+        Tx::new(
+            "test-chain",
+            from.to_string(),
+            "babayaga".to_string(),
+            Amount::from(1u64),
+            Amount::from(fee),
+            nonce,
+            data,
+        )
+    }
+
+    #[test]
+    fn full_pool_rejecting_a_replacement_keeps_the_original() {
+        let original = transfer("andrej", 5, 1, "");
+        let mut mempool = Mempool::default();
+        mempool.add(original.clone(), 0).unwrap();
+        mempool.add(transfer("cerf", 50, 1, ""), 0).unwrap();
+        let bytes = mempool.bytes();
+        mempool.config.max_bytes = bytes;
+
+        let replacement = transfer("andrej", 6, 1, &"x".repeat(64));
+        assert_eq!(
+            mempool.add(replacement, 1).unwrap_err(),
+            DatabaseError::MempoolFull
+        );
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool.bytes(), bytes);
+        let pending = mempool.pending_for(&"andrej".to_string());
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].tx, original);
+    }
+
+    #[test]
+    fn full_pool_rejecting_a_new_tx_keeps_the_txs_it_evicted() {
+        let mut mempool = Mempool::default();
+        mempool.add(transfer("andrej", 5, 1, ""), 0).unwrap();
+        mempool.add(transfer("cerf", 10, 1, ""), 0).unwrap();
+        mempool.add(transfer("caesar", 50, 1, ""), 0).unwrap();
+        let bytes = mempool.bytes();
+        mempool.config.max_bytes = bytes;
+
+        let large = transfer("babayaga", 7, 1, &"x".repeat(128));
+        assert_eq!(
+            mempool.add(large, 1).unwrap_err(),
+            DatabaseError::MempoolFull
+        );
+        assert_eq!(mempool.len(), 3);
+        assert_eq!(mempool.bytes(), bytes);
+        assert_eq!(mempool.pending_for(&"andrej".to_string()).len(), 1);
+    }
+
+    #[test]
+    fn full_pool_evicts_the_cheapest_last_nonce() {
+        let mut mempool = Mempool::new(MempoolConfig {
+            max_txs: 3,
+            ..MempoolConfig::default()
+        });
+        mempool.add(transfer("andrej", 1, 1, ""), 0).unwrap();
+        mempool.add(transfer("andrej", 100, 2, ""), 0).unwrap();
+        mempool.add(transfer("cerf", 5, 1, ""), 0).unwrap();
+
+        mempool.add(transfer("caesar", 10, 1, ""), 1).unwrap();
+        assert_eq!(mempool.len(), 3);
+        assert!(mempool.pending_for(&"cerf".to_string()).is_empty());
+        assert_eq!(mempool.pending_for(&"andrej".to_string()).len(), 2);
+
+        assert_eq!(
+            mempool.add(transfer("babayaga", 2, 1, ""), 1).unwrap_err(),
+            DatabaseError::MempoolFull
+        );
+        assert_eq!(mempool.len(), 3);
+    }
+
+    #[test]
+    fn replacement_needs_a_higher_fee() {
+        let original = transfer("andrej", 5, 1, "");
+        let mut mempool = Mempool::default();
+        mempool.add(original.clone(), 0).unwrap();
+
+        assert_eq!(
+            mempool.add(original.clone(), 1).unwrap_err(),
+            DatabaseError::DuplicateTx
+        );
+        assert_eq!(
+            mempool
+                .add(transfer("andrej", 5, 1, "again"), 1)
+                .unwrap_err(),
+            DatabaseError::ReplacementUnderpriced {
+                fee: Amount::from(5u64),
+                current: Amount::from(5u64),
+            }
+        );
+        let replacement = transfer("andrej", 6, 1, "");
+        assert_eq!(mempool.add(replacement.clone(), 1).unwrap(), Some(original));
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.txs()[0].tx, replacement);
+    }
+
+    #[test]
+    fn select_prefers_fees_within_each_senders_nonce_order() {
+        let mut mempool = Mempool::default();
+        mempool.add(transfer("andrej", 100, 2, ""), 0).unwrap();
+        mempool.add(transfer("andrej", 1, 1, ""), 0).unwrap();
+        mempool.add(transfer("cerf", 10, 1, ""), 0).unwrap();
+
+        assert_eq!(
+            mempool.select(|_| 1),
+            vec![
+                transfer("cerf", 10, 1, ""),
+                transfer("andrej", 1, 1, ""),
+                transfer("andrej", 100, 2, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn select_stops_at_a_nonce_gap() {
+        let mut mempool = Mempool::default();
+        mempool.add(transfer("andrej", 1, 1, ""), 0).unwrap();
+        mempool.add(transfer("andrej", 1, 3, ""), 0).unwrap();
+
+        assert_eq!(mempool.select(|_| 1), vec![transfer("andrej", 1, 1, "")]);
+        assert!(mempool.select(|_| 2).is_empty());
+    }
+
+    #[test]
+    fn expired_txs_take_later_txs_of_the_sender_with_them() {
+        let mut mempool = Mempool::new(MempoolConfig {
+            ttl_secs: 10,
+            ..MempoolConfig::default()
+        });
+        mempool.add(transfer("andrej", 1, 1, ""), 0).unwrap();
+        mempool.add(transfer("andrej", 1, 2, ""), 8).unwrap();
+        mempool.add(transfer("cerf", 1, 1, ""), 8).unwrap();
+
+        assert!(mempool.expire(10).is_empty());
+        let mut expired = mempool.expire(11);
+        expired.sort_by_key(|tx| tx.nonce());
+        assert_eq!(
+            expired,
+            vec![transfer("andrej", 1, 1, ""), transfer("andrej", 1, 2, "")]
+        );
+        assert_eq!(mempool.len(), 1);
+        assert_eq!(mempool.pending_for(&"cerf".to_string()).len(), 1);
+    }
+}
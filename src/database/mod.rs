@@ -1,11 +1,55 @@
+mod amount;
 mod block;
+mod config;
+mod consensus;
+mod dev;
+mod error;
+mod export;
+mod flat_store;
 mod fs;
 mod genesis;
+mod keys;
+mod kv_store;
+mod lock;
+mod memory_store;
+mod mempool;
+mod prune;
+mod snapshot;
 mod state;
+mod store;
+mod supply;
 mod tx;
+mod verify;
 
-pub use block::*;
-pub use fs::*;
-pub use genesis::*;
+pub(crate) use block::*;
+pub(crate) use flat_store::*;
+pub(crate) use fs::*;
+pub(crate) use genesis::*;
+pub(crate) use keys::*;
+pub(crate) use kv_store::*;
+pub(crate) use lock::*;
+pub(crate) use memory_store::*;
+pub(crate) use prune::*;
+pub(crate) use snapshot::*;
+pub(crate) use store::*;
+
+pub use amount::Amount;
+pub use block::{Block, BlockHeader, Hash};
+pub use config::Config;
+pub use consensus::{
+    BlockLimits, ConsensusConfig, ConsensusEngine, EngineConfig, FeeRules, RewardSchedule,
+};
+pub use dev::{dev_accounts, dev_chain_spec, DevAccount, DEV_ACCOUNT_BALANCE, DEV_CHAIN_ID};
+pub use error::DatabaseError;
+pub use export::{export_chain, import_chain, ChainFormat, ExportReport, ImportReport};
+pub use fs::{init_data_dir, init_data_dir_from_snapshot};
+pub use genesis::{ChainSpec, Genesis};
+pub use keys::{verify_tx_signature, SigningKey};
+pub use mempool::{FeeBucket, Mempool, MempoolConfig, PendingTx};
+pub use prune::{prune_data_dir, PruneReport, PruningConfig};
+pub use snapshot::{export_snapshot, Snapshot, SnapshotArchive, SnapshotConfig, SnapshotContents};
 pub use state::State;
-pub use tx::*;
+pub use store::{StorageBackend, StorageConfig};
+pub use supply::Supply;
+pub use tx::{new_account, Account, Tx, TxKind};
+pub use verify::{repair_chain, verify_chain, ChainReport, RepairReport, VerifyError};
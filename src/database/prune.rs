@@ -0,0 +1,161 @@
+use super::{
+    get_config_file_path, get_genesis_json_file_path, migrate_legacy_data_dir, open_stores,
+    BlockFS, BlockStore, ChainSpec, Config, DataDirLock, DatabaseError, HeaderFS, SharedBlockStore,
+    SharedStateStore, SnapshotArchive, SnapshotContents, State, StateStore,
+};
+
+pub(crate) const DEFAULT_PRUNE_INTERVAL: u64 = 1000;
+
+/// The `pruning` section of the datadir config. `keep_blocks = 0` keeps
+/// the full history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PruningConfig {
+    pub keep_blocks: u64,
+    pub interval: u64,
+}
+
+impl Default for PruningConfig {
+    fn default() -> Self {
+        PruningConfig {
+            keep_blocks: 0,
+            interval: DEFAULT_PRUNE_INTERVAL,
+        }
+    }
+}
+
+impl PruningConfig {
+    /// True if old blocks are pruned.
+    pub fn is_enabled(&self) -> bool {
+        self.keep_blocks > 0
+    }
+}
+
+/// What [`prune_data_dir`] removed. Blocks up to `anchor` keep only their
+/// headers.
+#[derive(Debug)]
+pub struct PruneReport {
+    pub anchor: u64,
+    pub pruned: u64,
+    pub kept: u64,
+}
+
+#[derive(Debug)]
+pub(crate) enum StoredBlock {
+    Full(BlockFS),
+    Pruned {
+        number: u64,
+        header: Option<HeaderFS>,
+    },
+}
+
+/// Drops the bodies of all but the latest `keep_blocks` blocks, or
+/// `pruning.keep_blocks` from the config if `None`. Returns `None` if there is
+/// nothing to prune.
+pub fn prune_data_dir(
+    data_dir: &str,
+    keep_blocks: Option<u64>,
+) -> Result<Option<PruneReport>, DatabaseError> {
+    let _lock = DataDirLock::acquire(data_dir)?;
+    migrate_legacy_data_dir(data_dir)?;
+    let config = Config::load(&get_config_file_path(data_dir)?)?;
+    let keep_blocks = keep_blocks.unwrap_or(config.pruning.keep_blocks);
+    let (blocks, store) = open_stores(data_dir, &config.storage, true)?;
+    prune_chain(data_dir, blocks, store, keep_blocks)
+}
+
+pub(crate) fn prune_chain(
+    data_dir: &str,
+    blocks: SharedBlockStore,
+    store: SharedStateStore,
+    keep_blocks: u64,
+) -> Result<Option<PruneReport>, DatabaseError> {
+    if keep_blocks == 0 {
+        return Ok(None);
+    }
+    let stored = blocks
+        .lock()
+        .unwrap()
+        .iter()?
+        .collect::<Result<Vec<BlockFS>, DatabaseError>>()?;
+    let Some(latest) = stored
+        .last()
+        .map(|block_fs| block_fs.value.header().number())
+    else {
+        return Ok(None);
+    };
+    let Some(anchor) = latest.checked_sub(keep_blocks) else {
+        return Ok(None);
+    };
+    if let Some(existing) = store.lock().unwrap().anchor()? {
+        if existing.block_number() >= anchor {
+            return Ok(None);
+        }
+    }
+    let (pruned, kept): (Vec<_>, Vec<_>) = stored
+        .into_iter()
+        .partition(|block_fs| block_fs.value.header().number() <= anchor);
+    if pruned.is_empty() {
+        return Ok(None);
+    }
+
+    let state = State::new_state_at(
+        data_dir,
+        Config::default(),
+        blocks.clone(),
+        store.clone(),
+        anchor,
+    )?;
+    let (Some(header), Some(snapshot)) = (state.latest_header().clone(), state.snapshot()) else {
+        return Err(DatabaseError::BlockNotFound(anchor));
+    };
+    let archive = SnapshotArchive::new(SnapshotContents {
+        chain_spec: ChainSpec::load(&get_genesis_json_file_path(data_dir)?)?,
+        anchor: header,
+        snapshot,
+    })?;
+    let headers: Vec<HeaderFS> = pruned
+        .iter()
+        .map(|block_fs| HeaderFS {
+            key: block_fs.key,
+            header: block_fs.value.header().clone(),
+        })
+        .collect();
+
+    let mut blocks = blocks.lock().unwrap();
+    let mut store = store.lock().unwrap();
+    blocks.add_headers(&headers)?;
+    store.set_anchor(&archive)?;
+    blocks.retain_after(anchor)?;
+    store.remove_snapshots_until(anchor)?;
+    Ok(Some(PruneReport {
+        anchor,
+        pruned: pruned.len() as u64,
+        kept: kept.len() as u64,
+    }))
+}
+
+pub(crate) fn read_stored_block(
+    blocks: &dyn BlockStore,
+    store: &dyn StateStore,
+    number: u64,
+) -> Result<StoredBlock, DatabaseError> {
+    if let Some(anchor) = store.anchor()? {
+        if number <= anchor.block_number() {
+            let header = if number == anchor.block_number() {
+                Some(HeaderFS {
+                    key: anchor.block_hash(),
+                    header: anchor.contents.anchor,
+                })
+            } else {
+                blocks.header(number)?
+            };
+            return Ok(StoredBlock::Pruned { number, header });
+        }
+    }
+
+    match blocks.get(number)? {
+        Some(block_fs) => Ok(StoredBlock::Full(block_fs)),
+        None => Err(DatabaseError::BlockNotFound(number)),
+    }
+}
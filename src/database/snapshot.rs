@@ -0,0 +1,337 @@
+use std::{collections::BTreeMap, io::Write};
+
+use data_encoding::HEXLOWER;
+
+use super::{
+    get_genesis_json_file_path, Account, Amount, BlockHeader, ChainSpec, DatabaseError, Hash,
+    State, Supply,
+};
+
+pub(crate) const DEFAULT_SNAPSHOT_INTERVAL: u64 = 1000;
+pub(crate) const DEFAULT_SNAPSHOTS_KEPT: usize = 2;
+pub(crate) const SNAPSHOT_FORMAT: &str = "tbb-state-snapshot";
+pub(crate) const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// The `snapshots` section of the datadir config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub interval: u64,
+    pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            interval: DEFAULT_SNAPSHOT_INTERVAL,
+            keep: DEFAULT_SNAPSHOTS_KEPT,
+        }
+    }
+}
+
+/// Balances, nonces and supply after block `block_number`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Snapshot {
+    pub genesis_hash: Hash,
+    pub block_number: u64,
+    pub block_hash: Hash,
+    pub supply: Supply,
+    pub balances: BTreeMap<Account, Amount>,
+    pub nonces: BTreeMap<Account, u64>,
+}
+
+impl Snapshot {
+    pub(crate) fn load(path: &str) -> Result<Snapshot, DatabaseError> {
+        let data = std::fs::read_to_string(path)?;
+        serde_json::from_str(&data)
+            .map_err(|err| DatabaseError::Corrupt(format!("snapshot {}: {}", path, err)))
+    }
+
+    pub(crate) fn write(&self, path: &str) -> Result<(), DatabaseError> {
+        let snapshot_json = serde_json::to_string(self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        let tmp_path = format!("{}.tmp", path);
+        let mut tmp_file = std::fs::File::create(&tmp_path)?;
+        writeln!(tmp_file, "{}", snapshot_json)?;
+        tmp_file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+pub(crate) fn write_snapshot(
+    dir: &str,
+    snapshot: &Snapshot,
+    keep: usize,
+) -> Result<String, DatabaseError> {
+    std::fs::create_dir_all(dir)?;
+    let path = std::path::Path::new(dir).join(format!("{:020}.json", snapshot.block_number));
+    let path = path.to_string_lossy().to_string();
+    snapshot.write(&path)?;
+    for (_, stale) in list_snapshots(dir)?.into_iter().skip(keep.max(1)) {
+        std::fs::remove_file(&stale)?;
+    }
+    Ok(path)
+}
+
+pub(crate) fn list_snapshots(dir: &str) -> Result<Vec<(u64, String)>, DatabaseError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(block_number) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        snapshots.push((block_number, path.to_string_lossy().to_string()));
+    }
+    snapshots.sort_by_key(|(block_number, _)| std::cmp::Reverse(*block_number));
+    Ok(snapshots)
+}
+
+/// A [`Snapshot`] with the chain spec and anchor block header needed to
+/// start a datadir from it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotContents {
+    pub chain_spec: ChainSpec,
+    pub anchor: BlockHeader,
+    pub snapshot: Snapshot,
+}
+
+/// A checksummed snapshot file, as written by [`export_snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotArchive {
+    pub format: String,
+    pub version: u32,
+    pub checksum: String,
+    pub contents: SnapshotContents,
+}
+
+impl SnapshotArchive {
+    /// Wraps `contents` with the current format version and its checksum.
+    pub fn new(contents: SnapshotContents) -> Result<SnapshotArchive, DatabaseError> {
+        Ok(SnapshotArchive {
+            format: SNAPSHOT_FORMAT.to_string(),
+            version: SNAPSHOT_FORMAT_VERSION,
+            checksum: checksum(&contents)?,
+            contents,
+        })
+    }
+
+    /// Reads and verifies an archive.
+    pub fn load(path: &str) -> Result<SnapshotArchive, DatabaseError> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|err| DatabaseError::BadSnapshot(format!("{}: {}", path, err)))?;
+        let archive: SnapshotArchive = serde_json::from_str(&data)
+            .map_err(|err| DatabaseError::BadSnapshot(format!("{}: {}", path, err)))?;
+        archive
+            .verify()
+            .map_err(|err| DatabaseError::BadSnapshot(format!("{}: {}", path, err)))?;
+        Ok(archive)
+    }
+
+    /// Writes the archive, replacing `path` atomically.
+    pub fn write(&self, path: &str) -> Result<(), DatabaseError> {
+        let archive_json = serde_json::to_string_pretty(self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        let tmp_path = format!("{}.tmp", path);
+        let mut tmp_file = std::fs::File::create(&tmp_path)?;
+        writeln!(tmp_file, "{}", archive_json)?;
+        tmp_file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Checks the format, checksum and that the snapshot belongs to the
+    /// chain spec.
+    pub fn verify(&self) -> Result<(), String> {
+        if self.format != SNAPSHOT_FORMAT {
+            return Err(format!("unknown format {:?}", self.format));
+        }
+        if self.version > SNAPSHOT_FORMAT_VERSION {
+            return Err(format!(
+                "format version {} is newer than the supported version {}",
+                self.version, SNAPSHOT_FORMAT_VERSION
+            ));
+        }
+        let computed = checksum(&self.contents).map_err(|err| err.to_string())?;
+        if computed != self.checksum {
+            return Err(format!(
+                "checksum {} does not match contents {}",
+                self.checksum, computed
+            ));
+        }
+        let contents = &self.contents;
+        let genesis_hash = contents.chain_spec.hash().map_err(|err| err.to_string())?;
+        if genesis_hash != contents.snapshot.genesis_hash {
+            return Err(format!(
+                "snapshot belongs to genesis {}, the chain spec hashes to {}",
+                HEXLOWER.encode(&contents.snapshot.genesis_hash),
+                HEXLOWER.encode(&genesis_hash)
+            ));
+        }
+        if contents.anchor.number() != contents.snapshot.block_number {
+            return Err(format!(
+                "anchor block {} does not match snapshot block {}",
+                contents.anchor.number(),
+                contents.snapshot.block_number
+            ));
+        }
+        let chain_id = contents.anchor.chain_id();
+        if !chain_id.is_empty() && chain_id != contents.chain_spec.chain_id {
+            return Err(format!(
+                "anchor block belongs to chain {:?}, not {:?}",
+                chain_id, contents.chain_spec.chain_id
+            ));
+        }
+        Ok(())
+    }
+
+    /// Chain the snapshot belongs to.
+    pub fn chain_id(&self) -> &str {
+        &self.contents.chain_spec.chain_id
+    }
+    /// Height of the anchor block.
+    pub fn block_number(&self) -> u64 {
+        self.contents.snapshot.block_number
+    }
+    /// Hash of the anchor block.
+    pub fn block_hash(&self) -> Hash {
+        self.contents.snapshot.block_hash
+    }
+}
+
+/// Archives the state after block `block_number` of `data_dir`, for
+/// [`init_data_dir_from_snapshot`](crate::database::init_data_dir_from_snapshot).
+pub fn export_snapshot(
+    data_dir: &str,
+    block_number: u64,
+) -> Result<SnapshotArchive, DatabaseError> {
+    let state = State::new_read_only_state_at(data_dir, block_number)?;
+    let chain_spec = ChainSpec::load(&get_genesis_json_file_path(data_dir)?)?;
+    let (Some(anchor), Some(snapshot)) = (state.latest_header().clone(), state.snapshot()) else {
+        return Err(DatabaseError::BadSnapshot(format!(
+            "{} has no block {}",
+            data_dir, block_number
+        )));
+    };
+    SnapshotArchive::new(SnapshotContents {
+        chain_spec,
+        anchor,
+        snapshot,
+    })
+}
+
+fn checksum(contents: &SnapshotContents) -> Result<String, DatabaseError> {
+    let contents_json = serde_json::to_string(contents)
+        .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+    let hash = ring::digest::digest(&ring::digest::SHA256, contents_json.as_bytes());
+    Ok(HEXLOWER.encode(hash.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        dev_accounts, dev_chain_spec, get_genesis_json_file_path, init_data_dir,
+        init_data_dir_from_snapshot, Block, StorageConfig, Tx, DEV_CHAIN_ID,
+    };
+
+    fn new_data_dir(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("tbb-snapshot-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    // A datadir with three blocks of one signed transfer each. Returns the
+    // last block and the final balances.
+    fn new_chain(data_dir: &str) -> (Block, BTreeMap<Account, Amount>) {
+        let accounts = dev_accounts(2).unwrap();
+        let spec = dev_chain_spec(&accounts, 0).unwrap();
+        init_data_dir(data_dir, &spec, StorageConfig::default()).unwrap();
+        let mut state = State::new_state_from_disk(data_dir).unwrap();
+        state.set_miner(accounts[0].account.clone());
+        for nonce in 1..=3 {
+            let tx = Tx::new(
+                DEV_CHAIN_ID,
+                accounts[0].account.clone(),
+                accounts[1].account.clone(),
+                Amount::new(7),
+                Amount::new(1),
+                nonce,
+                "",
+            );
+            let signature = accounts[0].signing_key().unwrap().sign_tx(&tx).unwrap();
+            state.add_tx(&tx.with_signature(&signature)).unwrap();
+            state.persist().unwrap();
+        }
+        let (blocks, _) = state.stores();
+        let last = blocks.lock().unwrap().get(2).unwrap().unwrap().value;
+        let balances = state.get_balances().clone().into_iter().collect();
+        (last, balances)
+    }
+
+    #[test]
+    fn exported_snapshot_restores_the_state_at_its_block() {
+        let data_dir = new_data_dir("source");
+        let restored_dir = new_data_dir("restored");
+        let (last_block, final_balances) = new_chain(&data_dir);
+
+        let archive = export_snapshot(&data_dir, 1).unwrap();
+        let archive_path = format!("{}/snapshot.json", data_dir);
+        archive.write(&archive_path).unwrap();
+        let loaded = SnapshotArchive::load(&archive_path).unwrap();
+        assert_eq!(loaded, archive);
+        init_data_dir_from_snapshot(&restored_dir, &loaded, StorageConfig::default()).unwrap();
+
+        let expected = State::new_read_only_state_at(&data_dir, 1).unwrap();
+        let mut restored = State::new_state_from_disk(&restored_dir).unwrap();
+        assert_eq!(restored.get_balances(), expected.get_balances());
+        assert_eq!(restored.supply(), expected.supply());
+        assert_eq!(restored.latest_block_hash(), archive.block_hash());
+        assert_eq!(restored.next_block_number(), 2);
+        restored.add_block(last_block).unwrap();
+        let balances: BTreeMap<Account, Amount> =
+            restored.get_balances().clone().into_iter().collect();
+        assert_eq!(balances, final_balances);
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+        std::fs::remove_dir_all(&restored_dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_with_a_mismatched_anchor_is_rejected() {
+        let data_dir = new_data_dir("mismatched");
+        let restored_dir = new_data_dir("mismatched-restored");
+        let (last_block, _) = new_chain(&data_dir);
+
+        let mut contents = export_snapshot(&data_dir, 1).unwrap().contents;
+        contents.anchor = last_block.header().clone();
+        let archive = SnapshotArchive::new(contents).unwrap();
+        assert_eq!(
+            archive.verify().unwrap_err(),
+            "anchor block 2 does not match snapshot block 1"
+        );
+        assert!(matches!(
+            init_data_dir_from_snapshot(&restored_dir, &archive, StorageConfig::default()),
+            Err(DatabaseError::BadSnapshot(_))
+        ));
+        assert!(
+            !std::path::Path::new(&get_genesis_json_file_path(&restored_dir).unwrap()).exists()
+        );
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+        let _ = std::fs::remove_dir_all(&restored_dir);
+    }
+}
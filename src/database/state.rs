@@ -1,215 +1,1529 @@
-use crate::database::{block::Block, BlockFS};
+use crate::database::{block::Block, BlockFS, BlockHeader, DatabaseError};
 
 use super::{
-    genesis::Genesis, get_blocks_db_file_path, get_genesis_json_file_path,
-    init_data_dir_if_not_exists, Account, Hash, Tx,
+    check_tx_signature, genesis::Genesis, get_genesis_json_file_path, init_data_dir_if_not_exists,
+    memory_stores, open_data_dir_read_only, open_data_dir_stores, prune_chain, read_legacy_height,
+    Account, Amount, ChainSpec, Config, ConsensusConfig, DataDirLock, Hash, Mempool, PruneReport,
+    PruningConfig, SharedBlockStore, SharedStateStore, Snapshot, SnapshotConfig, Supply, Tx,
 };
 use data_encoding::HEXLOWER;
-use std::{
-    collections::HashMap,
-    io::{BufRead, Write},
-    time,
-};
+use std::{collections::HashMap, sync::Arc, time};
 
+/// The ledger: balances, nonces and supply after the latest block, plus the
+/// mempool of txs waiting for the next one.
+///
+/// A state opened for writing holds the datadir lock until it is dropped and
+/// appends every block it accepts to the block store. A read-only state
+/// replays the chain without locking and rejects new blocks.
 #[derive(Debug)]
 pub struct State {
-    balances: HashMap<Account, u64>,
-    tx_mempool: Vec<Tx>,
-    db_file: std::fs::File,
-    latest_block: Option<Block>,
+    balances: HashMap<Account, Amount>,
+    supply: Supply,
+    nonces: HashMap<Account, u64>,
+    consensus: ConsensusConfig,
+    mempool: Mempool,
+    blocks: SharedBlockStore,
+    store: SharedStateStore,
+    latest_header: Option<BlockHeader>,
     latest_block_hash: Hash,
-    has_genesis_block: bool,
+    genesis_hash: Hash,
+    chain_id: String,
+    legacy_height: Option<u64>,
+    miner: Account,
+    lock: Option<Arc<DataDirLock>>,
+    writable: bool,
+    snapshots: SnapshotConfig,
+    pruning: PruningConfig,
+    data_dir: String,
 }
 
 impl State {
-    pub fn new_state_from_disk(data_dir: &str) -> State {
-        if let Err(err) = init_data_dir_if_not_exists(data_dir) {
-            panic!("State: Error initializing data directory: {:?}", err);
+    /// Opens `data_dir` for writing, creating it from the built-in genesis
+    /// if it is missing. Fails with [`DatabaseError::DataDirLocked`] if another
+    /// process has it open.
+    pub fn new_state_from_disk(data_dir: &str) -> Result<State, DatabaseError> {
+        let lock = DataDirLock::acquire(data_dir)?;
+        init_data_dir_if_not_exists(data_dir)?;
+        let mut state = State::load_from_disk(data_dir, Some(Arc::new(lock)), None)?;
+        if state.pruning.is_enabled() {
+            state.prune_history()?;
         }
-        let Ok(genesis_path) = get_genesis_json_file_path(data_dir) else {
-            panic!("State: Error getting genesis file path");
-        };
-        let genesis = Genesis::load_genesis(&genesis_path);
-        let mut balances = HashMap::new();
-        for (account, balance) in genesis.get_balances().iter() {
-            balances.insert(account.clone(), *balance);
-        }
-        let db_path = get_blocks_db_file_path(data_dir).unwrap();
-        let db_file = std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(db_path)
-            .unwrap();
+        Ok(state)
+    }
 
-        let mut state = State {
-            balances,
-            tx_mempool: Vec::new(),
-            db_file,
-            latest_block_hash: [0; 32],
-            latest_block: None,
-            has_genesis_block: false,
-        };
-        let Ok(cloned) = state.db_file.try_clone() else {
-            panic!("Error cloning db file");
-        };
-        let scanner = std::io::BufReader::new(cloned);
-        for line in scanner.lines() {
-            if let Ok(block_fs) = serde_json::from_str::<BlockFS>(&line.unwrap()) {
-                if state.apply_txs(block_fs.value.txs()).is_ok() {
-                    state.latest_block = Some(block_fs.value.clone());
-                    state.latest_block_hash = block_fs.key;
-                    state.has_genesis_block = true;
-                };
+    /// Replays the chain in `data_dir` without taking the lock.
+    pub fn new_read_only_state_from_disk(data_dir: &str) -> Result<State, DatabaseError> {
+        open_data_dir_read_only(data_dir)?;
+        State::load_from_disk(data_dir, None, None)
+    }
+
+    pub(crate) fn new_read_only_state_at(
+        data_dir: &str,
+        block_number: u64,
+    ) -> Result<State, DatabaseError> {
+        open_data_dir_read_only(data_dir)?;
+        let (config, blocks, store) = open_data_dir_stores(data_dir, false)?;
+        State::new_state_at(data_dir, config, blocks, store, block_number)
+    }
+
+    pub(crate) fn new_state_at(
+        data_dir: &str,
+        config: Config,
+        blocks: SharedBlockStore,
+        store: SharedStateStore,
+        block_number: u64,
+    ) -> Result<State, DatabaseError> {
+        let state = State::load(data_dir, config, blocks, store, None, Some(block_number))?;
+        match state.latest_header {
+            Some(ref header) if header.number() == block_number => Ok(state),
+            Some(ref header) if header.number() > block_number => {
+                Err(DatabaseError::BadSnapshot(format!(
+                    "{} starts at snapshot block {}, block {} is not available",
+                    data_dir,
+                    header.number(),
+                    block_number
+                )))
+            }
+            _ => Err(DatabaseError::BadSnapshot(format!(
+                "{} has no block {}, the chain has {} blocks",
+                data_dir,
+                block_number,
+                state.next_block_number()
+            ))),
+        }
+    }
+
+    fn load_from_disk(
+        data_dir: &str,
+        lock: Option<Arc<DataDirLock>>,
+        until: Option<u64>,
+    ) -> Result<State, DatabaseError> {
+        let (config, blocks, store) = open_data_dir_stores(data_dir, lock.is_some())?;
+        State::load(data_dir, config, blocks, store, lock, until)
+    }
+
+    pub(crate) fn load(
+        data_dir: &str,
+        config: Config,
+        blocks: SharedBlockStore,
+        store: SharedStateStore,
+        lock: Option<Arc<DataDirLock>>,
+        until: Option<u64>,
+    ) -> Result<State, DatabaseError> {
+        let mut state = State::new_base_state(data_dir, blocks, store)?;
+        state.mempool = Mempool::new(config.mempool);
+        state.writable = lock.is_some();
+        state.lock = lock;
+        state.snapshots = config.snapshots;
+        state.pruning = config.pruning;
+        state.data_dir = data_dir.to_string();
+        let mut stored: Vec<Result<BlockFS, DatabaseError>> =
+            state.blocks.lock().unwrap().iter()?.collect();
+        let mut stale = 0;
+        for block_fs in stored.iter() {
+            if !state.is_before_base(block_fs.as_ref().map_err(|err| err.clone())?)? {
+                break;
             }
+            stale += 1;
         }
+        stored.drain(..stale);
+
+        let mut replay_from = 0;
+        let snapshots = state.store.lock().unwrap().snapshots()?;
+        for block_number in snapshots {
+            if until.is_some_and(|until| block_number > until) {
+                continue;
+            }
+            match state.restore_snapshot(block_number, &stored) {
+                Ok(next) => {
+                    println!("Loaded state snapshot at block {}", block_number);
+                    replay_from = next;
+                    break;
+                }
+                Err(err) => println!("Ignoring snapshot at block {}: {}", block_number, err),
+            }
+        }
+        for block_fs in stored.iter().skip(replay_from) {
+            if until.is_some_and(|until| state.next_block_number() > until) {
+                break;
+            }
+            let repair_hint = |err: DatabaseError| {
+                let message = match err {
+                    DatabaseError::Corrupt(message) => message,
+                    err => err.to_string(),
+                };
+                DatabaseError::Corrupt(format!(
+                    "{}, run `tbb chain repair --datadir {}`",
+                    message, data_dir
+                ))
+            };
+            let block_fs = block_fs.as_ref().map_err(|err| repair_hint(err.clone()))?;
+            state.apply_block_fs(block_fs).map_err(|err| {
+                repair_hint(DatabaseError::Corrupt(format!(
+                    "block {}: {}",
+                    block_fs.value.header().number(),
+                    err
+                )))
+            })?;
+        }
+        Ok(state)
+    }
+
+    pub(crate) fn new_base_state(
+        data_dir: &str,
+        blocks: SharedBlockStore,
+        store: SharedStateStore,
+    ) -> Result<State, DatabaseError> {
+        let genesis_path = get_genesis_json_file_path(data_dir)?;
+        let mut state = State::new_genesis_state(&genesis_path, blocks, store)?;
+        state.legacy_height = read_legacy_height(data_dir)?;
+        let Some(archive) = state.store.lock().unwrap().anchor()? else {
+            return Ok(state);
+        };
+        let contents = archive.contents;
         state
+            .restore(contents.snapshot, contents.anchor)
+            .map_err(|err| DatabaseError::BadSnapshot(format!("anchor: {}", err)))?;
+        Ok(state)
     }
 
-    pub fn apply_block(&mut self, block: &Block, state: &State) -> Result<(), String> {
-        let next_expected_block_number = match state.latest_block {
-            Some(ref block) => block.header().number() + 1,
-            None => 0,
+    fn restore_snapshot(
+        &mut self,
+        block_number: u64,
+        stored: &[Result<BlockFS, DatabaseError>],
+    ) -> Result<usize, DatabaseError> {
+        let snapshot = self.store.lock().unwrap().snapshot(block_number)?;
+        let first = self.next_block_number();
+        if snapshot.block_number < first {
+            return Err(DatabaseError::Corrupt(format!(
+                "snapshot is older than the first stored block {}",
+                first
+            )));
+        }
+        let mut parent = self.latest_block_hash;
+        for (offset, block_fs) in stored.iter().enumerate() {
+            let number = first + offset as u64;
+            let block_fs = block_fs.as_ref().map_err(|err| err.clone())?;
+            let header = block_fs.value.header();
+            if header.number() != number {
+                return Err(DatabaseError::InvalidBlockNumber {
+                    expected: number,
+                    found: header.number(),
+                });
+            }
+            let legacy_root = self.latest_header.is_none() && *header.parent() == [0; 32];
+            if *header.parent() != parent && !(offset == 0 && legacy_root) {
+                return Err(DatabaseError::ParentMismatch {
+                    expected: parent,
+                    found: *header.parent(),
+                });
+            }
+            if header.number() < snapshot.block_number {
+                parent = block_fs.key;
+                continue;
+            }
+            let block_hash = block_fs.verify_key()?;
+            if block_hash != snapshot.block_hash {
+                return Err(DatabaseError::Corrupt(format!(
+                    "snapshot claims block {} is {}, the block store has {}",
+                    snapshot.block_number,
+                    HEXLOWER.encode(&snapshot.block_hash),
+                    HEXLOWER.encode(&block_hash)
+                )));
+            }
+            self.restore(snapshot, block_fs.value.header().clone())?;
+            return Ok(offset + 1);
+        }
+        Err(DatabaseError::Corrupt(format!(
+            "snapshot is at block {} but the block store ends before it",
+            snapshot.block_number
+        )))
+    }
+
+    fn restore(&mut self, snapshot: Snapshot, header: BlockHeader) -> Result<(), DatabaseError> {
+        if snapshot.genesis_hash != self.genesis_hash {
+            return Err(DatabaseError::Corrupt(format!(
+                "snapshot belongs to genesis {}",
+                HEXLOWER.encode(&snapshot.genesis_hash)
+            )));
+        }
+        if snapshot.supply.genesis != self.supply.genesis {
+            return Err(DatabaseError::Corrupt(format!(
+                "snapshot genesis supply {} TBB does not match {} TBB",
+                snapshot.supply.genesis, self.supply.genesis
+            )));
+        }
+        let mut restored = self.copy()?;
+        restored.balances = snapshot.balances.into_iter().collect();
+        restored.nonces = snapshot.nonces.into_iter().collect();
+        restored.supply = snapshot.supply;
+        restored.check_supply_invariant()?;
+        self.balances = restored.balances;
+        self.nonces = restored.nonces;
+        self.supply = restored.supply;
+        self.latest_header = Some(header);
+        self.latest_block_hash = snapshot.block_hash;
+        Ok(())
+    }
+
+    /// Balances, nonces and supply as of the latest block, `None` before
+    /// block 0.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        let latest_header = self.latest_header.as_ref()?;
+        Some(Snapshot {
+            genesis_hash: self.genesis_hash,
+            block_number: latest_header.number(),
+            block_hash: self.latest_block_hash,
+            supply: self.supply,
+            balances: self
+                .balances
+                .iter()
+                .map(|(account, balance)| (account.clone(), *balance))
+                .collect(),
+            nonces: self
+                .nonces
+                .iter()
+                .map(|(account, nonce)| (account.clone(), *nonce))
+                .collect(),
+        })
+    }
+
+    pub(crate) fn is_before_base(&self, block_fs: &BlockFS) -> Result<bool, DatabaseError> {
+        let Some(ref base) = self.latest_header else {
+            return Ok(false);
         };
-        if state.has_genesis_block && block.header().number() != next_expected_block_number {
-            return Err(format!(
-                "next exptected number block must be {} not {}",
-                next_expected_block_number,
-                block.header().number()
-            ));
+        let number = block_fs.value.header().number();
+        if number == base.number() && block_fs.key != self.latest_block_hash {
+            return Err(DatabaseError::Corrupt(format!(
+                "block {} is {}, the snapshot anchor is {}",
+                number,
+                HEXLOWER.encode(&block_fs.key),
+                HEXLOWER.encode(&self.latest_block_hash)
+            )));
         }
-        let blocks_equals = state.latest_block == Some(block.clone());
-        if state.has_genesis_block
-            && state.latest_block.clone().unwrap().header().number() > 0
-            && !blocks_equals
+        Ok(number <= base.number())
+    }
+
+    fn write_snapshot_if_due(&mut self) -> Result<(), DatabaseError> {
+        let Some(snapshot) = self.snapshot() else {
+            return Ok(());
+        };
+        let interval = self.snapshots.interval;
+        if !self.writable
+            || interval == 0
+            || snapshot.block_number == 0
+            || snapshot.block_number % interval != 0
         {
-            return Err(format!(
-                "next block parent hash must be {:?} not {:?}",
-                state.latest_block_hash,
-                block.header().parent()
-            ));
+            return Ok(());
+        }
+        self.blocks.lock().unwrap().sync()?;
+        self.store
+            .lock()
+            .unwrap()
+            .write_snapshot(&snapshot, self.snapshots.keep)?;
+        println!("Wrote state snapshot at block {}", snapshot.block_number);
+        Ok(())
+    }
+
+    pub(crate) fn prune_history(&mut self) -> Result<Option<PruneReport>, DatabaseError> {
+        if !self.writable || self.data_dir.is_empty() {
+            return Err(DatabaseError::ReadOnly);
+        }
+        self.blocks.lock().unwrap().sync()?;
+        let Some(report) = prune_chain(
+            &self.data_dir,
+            self.blocks.clone(),
+            self.store.clone(),
+            self.pruning.keep_blocks,
+        )?
+        else {
+            return Ok(None);
+        };
+        println!(
+            "Pruned {} block bodies up to block {}, keeping {}",
+            report.pruned, report.anchor, report.kept
+        );
+        Ok(Some(report))
+    }
+
+    fn prune_if_due(&mut self) -> Result<(), DatabaseError> {
+        let interval = self.pruning.interval.max(1);
+        let due = match self.latest_header {
+            Some(ref header) => header.number() % interval == 0,
+            None => false,
+        };
+        if self.writable && self.pruning.is_enabled() && due {
+            self.prune_history()?;
+        }
+        Ok(())
+    }
+
+    /// A writable state for a new chain from `spec` whose blocks are kept in
+    /// memory only.
+    pub fn new_in_memory_state(spec: ChainSpec, config: Config) -> Result<State, DatabaseError> {
+        let genesis = Genesis::from_spec(spec)?;
+        let (blocks, store) = memory_stores();
+        let mut state = State::from_genesis(&genesis, blocks, store);
+        state.mempool = Mempool::new(config.mempool);
+        state.writable = true;
+        state.snapshots = config.snapshots;
+        Ok(state)
+    }
+
+    pub(crate) fn new_genesis_state(
+        genesis_path: &str,
+        blocks: SharedBlockStore,
+        store: SharedStateStore,
+    ) -> Result<State, DatabaseError> {
+        let genesis = Genesis::load_genesis(genesis_path)?;
+        Ok(State::from_genesis(&genesis, blocks, store))
+    }
+
+    fn from_genesis(genesis: &Genesis, blocks: SharedBlockStore, store: SharedStateStore) -> State {
+        let balances: HashMap<Account, Amount> = genesis
+            .get_balances()
+            .iter()
+            .map(|(account, balance)| (account.clone(), *balance))
+            .collect();
+        let supply = Supply {
+            genesis: genesis.supply(),
+            ..Supply::default()
+        };
+        State {
+            balances,
+            supply,
+            nonces: HashMap::new(),
+            consensus: genesis.consensus().clone(),
+            mempool: Mempool::default(),
+            blocks,
+            store,
+            latest_block_hash: genesis.hash(),
+            genesis_hash: genesis.hash(),
+            chain_id: genesis.chain_id().to_string(),
+            legacy_height: None,
+            latest_header: None,
+            miner: Account::new(),
+            lock: None,
+            writable: false,
+            snapshots: SnapshotConfig::default(),
+            pruning: PruningConfig::default(),
+            data_dir: String::new(),
+        }
+    }
+
+    /// Checks that `block` can extend the chain: chain id, height, parent,
+    /// time, producer, limits, coinbase and every tx, without changing anything.
+    pub fn validate_block(&self, block: &Block) -> Result<(), DatabaseError> {
+        let header = block.header();
+        self.check_chain_id(header.chain_id(), header.number())?;
+        let next_expected_block_number = self.next_block_number();
+        if header.number() != next_expected_block_number {
+            return Err(DatabaseError::InvalidBlockNumber {
+                expected: next_expected_block_number,
+                found: header.number(),
+            });
+        }
+        let legacy_root = self.latest_header.is_none() && *header.parent() == [0; 32];
+        if *header.parent() != self.latest_block_hash && !legacy_root {
+            return Err(DatabaseError::ParentMismatch {
+                expected: self.latest_block_hash,
+                found: *header.parent(),
+            });
+        }
+        if let Some(ref latest_header) = self.latest_header {
+            if header.time() < latest_header.time() {
+                return Err(DatabaseError::TimeBeforeParent {
+                    parent: latest_header.time(),
+                    found: header.time(),
+                });
+            }
+        }
+        if !self.consensus.engine.is_producer(header.miner()) {
+            return Err(DatabaseError::UnauthorizedProducer(header.miner().clone()));
+        }
+        if block.txs().len() > self.consensus.limits.max_block_txs {
+            return Err(DatabaseError::TooManyTxs {
+                max: self.consensus.limits.max_block_txs,
+                found: block.txs().len(),
+            });
+        }
+        let size = block.size()?;
+        if size > self.consensus.limits.max_block_bytes {
+            return Err(DatabaseError::BlockTooLarge {
+                max: self.consensus.limits.max_block_bytes,
+                found: size,
+            });
+        }
+        for (index, tx) in block.txs().iter().enumerate() {
+            self.check_chain_id(tx.chain_id(), header.number())?;
+            self.check_tx_data(tx)?;
+            if !tx.is_coinbase() {
+                check_tx_signature(tx)?;
+                continue;
+            }
+            if index != 0 {
+                return Err(DatabaseError::InvalidCoinbase(format!(
+                    "coinbase must be the first tx of the block, found at index {}",
+                    index
+                )));
+            }
+            if !tx.from().is_empty() || !tx.fee().is_zero() {
+                return Err(DatabaseError::InvalidCoinbase(
+                    "coinbase must not have a sender or a fee".to_string(),
+                ));
+            }
+            let reward = self.block_reward(header.number());
+            if tx.value() > reward {
+                return Err(DatabaseError::InvalidCoinbase(format!(
+                    "coinbase of {} TBB exceeds the block {} reward of {} TBB",
+                    tx.value(),
+                    header.number(),
+                    reward
+                )));
+            }
+        }
+        Ok(())
+    }
+    fn is_legacy(&self, block_number: u64) -> bool {
+        self.legacy_height
+            .is_some_and(|legacy_height| block_number <= legacy_height)
+    }
+    fn check_chain_id(&self, chain_id: &str, block_number: u64) -> Result<(), DatabaseError> {
+        if chain_id.is_empty() && self.is_legacy(block_number) {
+            return Ok(());
+        }
+        if chain_id != self.chain_id {
+            return Err(DatabaseError::WrongChain {
+                expected: self.chain_id.clone(),
+                found: chain_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+    fn check_tx_data(&self, tx: &Tx) -> Result<(), DatabaseError> {
+        if tx.data().len() > self.consensus.limits.max_tx_data_bytes {
+            return Err(DatabaseError::DataTooLarge {
+                max: self.consensus.limits.max_tx_data_bytes,
+                found: tx.data().len(),
+            });
         }
-        self.apply_txs(block.txs())?;
         Ok(())
     }
-    fn apply_txs(&mut self, txs: &Vec<Tx>) -> Result<(), String> {
+    pub(crate) fn apply_block(&mut self, block: &Block) -> Result<Hash, DatabaseError> {
+        self.validate_block(block)?;
+        let block_hash = block.hash()?;
+        if self.is_legacy(block.header().number()) {
+            for tx in block.txs() {
+                self.apply_legacy_tx(tx)?;
+            }
+        } else {
+            self.apply_txs(block.txs(), block.header().miner())?;
+        }
+        if cfg!(debug_assertions) {
+            self.check_supply_invariant()?;
+        }
+        self.latest_header = Some(block.header().clone());
+        self.latest_block_hash = block_hash;
+        Ok(block_hash)
+    }
+    pub(crate) fn apply_block_fs(&mut self, block_fs: &BlockFS) -> Result<Hash, DatabaseError> {
+        block_fs.verify_key()?;
+        self.apply_block(&block_fs.value)
+    }
+    fn apply_txs(&mut self, txs: &Vec<Tx>, miner: &Account) -> Result<(), DatabaseError> {
         for tx in txs {
-            self.apply_tx(tx)?;
+            self.apply_tx(tx, miner)?;
         }
         Ok(())
     }
-    pub fn add_blocks(&mut self, blocks: Vec<Block>) -> Result<(), String> {
+    /// Adds `blocks` in order, stopping at the first one that fails.
+    pub fn add_blocks(&mut self, blocks: Vec<Block>) -> Result<(), DatabaseError> {
         for block in blocks {
             self.add_block(block)?;
         }
         Ok(())
     }
-    pub fn add_block(&mut self, block: Block) -> Result<Hash, String> {
-        let pending_state = self.copy();
-        self.apply_block(&block, &pending_state)?;
-        let block_hash = block.hash()?;
-        let block_fs = BlockFS {
-            key: block_hash,
-            value: block,
-        };
-        let block_fs_json = serde_json::to_string(&block_fs).unwrap();
-        println!("Persisting new block to disk");
-
-        writeln!(self.db_file, "{}", block_fs_json).unwrap();
+    /// Validates `block`, applies it and appends it to the block store. On
+    /// error the state is unchanged. Fails with [`DatabaseError::ReadOnly`] on a
+    /// read-only state.
+    pub fn add_block(&mut self, block: Block) -> Result<Hash, DatabaseError> {
+        let mut pending_state = self.copy()?;
+        let block_hash = pending_state.apply_block(&block)?;
+        self.write_block(block_hash, block)?;
         self.balances = pending_state.balances;
-        self.latest_block_hash = block_hash;
-        self.latest_block = Some(block_fs.value);
-        self.has_genesis_block = true;
+        self.supply = pending_state.supply;
+        self.nonces = pending_state.nonces;
+        if let Err(err) = self.write_snapshot_if_due() {
+            println!("Failed to write state snapshot: {}", err);
+        }
+        if let Err(err) = self.prune_if_due() {
+            println!("Failed to prune history: {}", err);
+        }
 
         Ok(block_hash)
     }
-    pub fn add_tx(&mut self, tx: &Tx) -> Result<(), String> {
-        self.apply_tx(tx)?;
-        self.tx_mempool.push(tx.clone());
+    /// Checks `tx` against the balances and nonces after the txs already
+    /// pending from its sender and adds it to the mempool. A tx reusing a pending
+    /// nonce with a higher fee replaces the pending one.
+    pub fn add_tx(&mut self, tx: &Tx) -> Result<(), DatabaseError> {
+        if tx.value().is_zero() {
+            return Err(DatabaseError::InvalidAmount { value: tx.value() });
+        }
+        self.check_chain_id(tx.chain_id(), self.next_block_number())?;
+        self.check_tx_data(tx)?;
+        if tx.is_coinbase() {
+            return Err(DatabaseError::CoinbaseNotAllowed);
+        }
+        check_tx_signature(tx)?;
+        if tx.fee() < self.consensus.fees.min_tx_fee {
+            return Err(DatabaseError::FeeTooLow {
+                fee: tx.fee(),
+                min: self.consensus.fees.min_tx_fee,
+            });
+        }
+        if !self.balances.contains_key(tx.from()) {
+            return Err(DatabaseError::UnknownAccount(tx.from().clone()));
+        }
+        let now = unix_now()?;
+        self.mempool.expire(now);
+        let expected = self.next_nonce(tx.from());
+        let pending: Vec<&Tx> = self
+            .mempool
+            .pending_for(tx.from())
+            .into_iter()
+            .map(|pending| &pending.tx)
+            .collect();
+        let next_free = expected + pending.len() as u64;
+        if tx.nonce() < expected || tx.nonce() > next_free {
+            return Err(DatabaseError::InvalidNonce {
+                account: tx.from().clone(),
+                expected: if tx.nonce() < expected {
+                    expected
+                } else {
+                    next_free
+                },
+                found: tx.nonce(),
+            });
+        }
+        let overflow = || DatabaseError::Overflow {
+            account: tx.from().clone(),
+        };
+        let mut cost = tx.cost().ok_or_else(overflow)?;
+        for pending in pending
+            .iter()
+            .filter(|pending| pending.nonce() != tx.nonce())
+        {
+            cost = pending
+                .cost()
+                .and_then(|pending_cost| cost.checked_add(pending_cost))
+                .ok_or_else(overflow)?;
+        }
+        let balance = *self.balances.get(tx.from()).unwrap_or(&Amount::ZERO);
+        if cost > balance {
+            return Err(DatabaseError::InsufficientBalance {
+                account: tx.from().clone(),
+                balance,
+                cost,
+            });
+        }
+        self.mempool.add(tx.clone(), now)?;
         Ok(())
     }
 
-    pub fn persist(&mut self) -> Result<Hash, String> {
-        let block_height = match self.latest_block {
-            Some(ref block) => block.header().number() + 1,
-            None => 0,
-        };
+    /// Seals the mempool into the next block: the coinbase for the miner set
+    /// with [`State::set_miner`], then pending txs by fee within the block limits.
+    /// Txs that no longer apply are dropped from the mempool. Returns the new
+    /// block's hash.
+    pub fn persist(&mut self) -> Result<Hash, DatabaseError> {
+        let block_height = self.next_block_number();
+        let now = unix_now()?;
+        self.mempool.expire(now);
+
+        let nonces = &self.nonces;
+        let candidates = self.mempool.select(|account| next_nonce(nonces, account));
+        let mut pending_state = self.copy()?;
+        let mut txs = Vec::new();
+        let reward = self.block_reward(block_height);
+        if !self.miner.is_empty() && !reward.is_zero() {
+            let coinbase = Tx::coinbase(&self.chain_id, self.miner.clone(), reward, block_height);
+            pending_state.apply_tx(&coinbase, &self.miner)?;
+            txs.push(coinbase);
+        }
+        let reserved_txs = txs.len();
+        let mut block_size = Block::new(
+            &self.chain_id,
+            self.latest_block_hash,
+            now,
+            txs.clone(),
+            block_height,
+            self.miner.clone(),
+        )
+        .size()?;
+        for tx in candidates {
+            if txs.len() >= self.consensus.limits.max_block_txs {
+                break;
+            }
+            let tx_size = tx.size()? + if txs.is_empty() { 0 } else { 1 };
+            if block_size + tx_size > self.consensus.limits.max_block_bytes {
+                if txs.len() == reserved_txs {
+                    println!("Dropping pending tx {:?}: does not fit in a block", tx);
+                    self.mempool.remove_by_hash(&tx.hash()?);
+                    continue;
+                }
+                break;
+            }
+            match pending_state.apply_tx(&tx, &self.miner) {
+                Ok(()) => {
+                    block_size += tx_size;
+                    txs.push(tx);
+                }
+                Err(err) => {
+                    println!("Dropping pending tx {:?}: {}", tx, err);
+                    self.mempool.remove_by_hash(&tx.hash()?);
+                }
+            }
+        }
+
         let block = Block::new(
+            &self.chain_id,
             self.latest_block_hash,
-            time::SystemTime::now()
-                .duration_since(time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            self.tx_mempool.clone(),
+            now,
+            txs.clone(),
             block_height,
+            self.miner.clone(),
         );
-        let block_hash = block.hash()?;
+        let block_hash = self.add_block(block)?;
+        println!("Block created {:?}", HEXLOWER.encode(&block_hash));
+        self.mempool.remove_included(&txs);
+        let nonces = &self.nonces;
+        self.mempool.prune(|account| next_nonce(nonces, account));
+
+        Ok(block_hash)
+    }
+
+    fn write_block(&mut self, block_hash: Hash, block: Block) -> Result<(), DatabaseError> {
+        if !self.writable {
+            return Err(DatabaseError::ReadOnly);
+        }
         let block_fs = BlockFS {
             key: block_hash,
             value: block,
         };
-        let block_fs_json = serde_json::to_string(&block_fs).unwrap();
         println!("Persisting new block to disk");
-        println!("Block created {:?}", HEXLOWER.encode(&block_hash));
 
-        if self.db_file.write(block_fs_json.as_bytes()).is_err() {
-            return Err("Error writing to file".to_string());
-        }
-        if self.db_file.write("\n".as_bytes()).is_err() {
-            return Err("Error writing to file".to_string());
-        }
+        self.blocks.lock().unwrap().append(&block_fs)?;
         self.latest_block_hash = block_hash;
-        self.tx_mempool.clear();
-
-        Ok(self.latest_block_hash)
+        self.latest_header = Some(block_fs.value.header().clone());
+        Ok(())
     }
 
-    pub fn close(&self) {
-        self.db_file.sync_all().unwrap();
+    /// Flushes the block store to disk.
+    pub fn close(&self) -> Result<(), DatabaseError> {
+        self.blocks.lock().unwrap().sync()
     }
-    pub fn copy(&self) -> State {
-        State {
+    pub(crate) fn copy(&self) -> Result<State, DatabaseError> {
+        Ok(State {
             balances: self.balances.clone(),
-            tx_mempool: self.tx_mempool.clone(),
-            db_file: self.db_file.try_clone().unwrap(),
-            latest_block: self.latest_block.clone(),
+            supply: self.supply,
+            nonces: self.nonces.clone(),
+            consensus: self.consensus.clone(),
+            mempool: self.mempool.clone(),
+            blocks: self.blocks.clone(),
+            store: self.store.clone(),
+            latest_header: self.latest_header.clone(),
             latest_block_hash: self.latest_block_hash,
-            has_genesis_block: self.has_genesis_block,
-        }
+            genesis_hash: self.genesis_hash,
+            chain_id: self.chain_id.clone(),
+            legacy_height: self.legacy_height,
+            miner: self.miner.clone(),
+            lock: self.lock.clone(),
+            writable: self.writable,
+            snapshots: self.snapshots,
+            pruning: self.pruning,
+            data_dir: self.data_dir.clone(),
+        })
+    }
+    /// Txs waiting for the next block.
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+    /// Drops a pending tx by hash, returning it if it was pending.
+    pub fn remove_pending_tx(&mut self, hash: &Hash) -> Option<Tx> {
+        self.mempool.remove_by_hash(hash).map(|pending| pending.tx)
+    }
+    /// Nonce of the next tx from `account` to be sealed.
+    pub fn next_nonce(&self, account: &Account) -> u64 {
+        next_nonce(&self.nonces, account)
+    }
+    /// Nonce a new tx from `account` should use, counting its pending txs.
+    pub fn next_pending_nonce(&self, account: &Account) -> u64 {
+        self.next_nonce(account) + self.mempool.pending_for(account).len() as u64
+    }
+    /// Account credited by [`State::persist`]. No coinbase is added while it
+    /// is empty.
+    pub fn set_miner(&mut self, miner: Account) {
+        self.miner = miner;
     }
-    pub fn get_balances(&self) -> &HashMap<Account, u64> {
+    /// Balances by account after the latest block.
+    pub fn get_balances(&self) -> &HashMap<Account, Amount> {
         &self.balances
     }
+    /// Genesis, minted and burned totals after the latest block.
+    pub fn supply(&self) -> &Supply {
+        &self.supply
+    }
+    /// Sum of all balances, `None` on overflow.
+    pub fn circulating_supply(&self) -> Option<Amount> {
+        self.balances
+            .values()
+            .try_fold(Amount::ZERO, |total, balance| total.checked_add(*balance))
+    }
+    /// The chain's consensus rules.
+    pub fn consensus(&self) -> &ConsensusConfig {
+        &self.consensus
+    }
+    /// Height of the next block, 0 for an empty chain.
+    pub fn next_block_number(&self) -> u64 {
+        match self.latest_header {
+            Some(ref header) => header.number() + 1,
+            None => 0,
+        }
+    }
+    /// Reward for block `block_number`: the scheduled reward, capped so the
+    /// supply never exceeds `rewards.max_supply`.
+    pub fn block_reward(&self, block_number: u64) -> Amount {
+        let issued = self
+            .supply
+            .issued()
+            .unwrap_or(self.consensus.rewards.max_supply);
+        let remaining = self
+            .consensus
+            .rewards
+            .max_supply
+            .checked_sub(issued)
+            .unwrap_or(Amount::ZERO);
+        self.consensus
+            .rewards
+            .scheduled_reward(block_number)
+            .min(remaining)
+    }
+    /// Height where the reward next halves, `None` once rewards have ended.
+    pub fn next_halving_height(&self, block_number: u64) -> Option<u64> {
+        if self.block_reward(block_number).is_zero() {
+            return None;
+        }
+        self.consensus.rewards.next_halving_height(block_number)
+    }
+    /// Checks that the balances add up to the supply.
+    pub fn check_supply_invariant(&self) -> Result<(), DatabaseError> {
+        let expected = self.supply.total().ok_or(DatabaseError::SupplyOverflow)?;
+        let found = self
+            .circulating_supply()
+            .ok_or(DatabaseError::SupplyOverflow)?;
+        if expected != found {
+            return Err(DatabaseError::SupplyMismatch { expected, found });
+        }
+        Ok(())
+    }
+    /// The chain id from the genesis.
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+    pub(crate) fn stores(&self) -> (SharedBlockStore, SharedStateStore) {
+        (self.blocks.clone(), self.store.clone())
+    }
+    /// History pruning settings from the datadir config.
+    pub fn pruning(&self) -> &PruningConfig {
+        &self.pruning
+    }
+    /// Hash of the chain spec the chain started from.
+    pub fn genesis_hash(&self) -> Hash {
+        self.genesis_hash
+    }
+    /// Hash of the latest block, the genesis hash before block 0.
     pub fn latest_block_hash(&self) -> Hash {
         self.latest_block_hash
     }
-    pub fn latest_block(&self) -> &Option<Block> {
-        &self.latest_block
+    /// Header of the latest block, `None` before block 0.
+    pub fn latest_header(&self) -> &Option<BlockHeader> {
+        &self.latest_header
     }
-    pub fn apply_tx(&mut self, tx: &Tx) -> Result<(), String> {
+    pub(crate) fn apply_tx(&mut self, tx: &Tx, miner: &Account) -> Result<(), DatabaseError> {
         println!("Applying tx {:?}", tx);
-        let from_balance = self.balances.get(tx.from()).unwrap_or(&0);
-        let to_balance = self.balances.get(tx.to()).unwrap_or(&0);
-        if tx.is_reward() {
-            self.balances
-                .insert(tx.to().clone(), to_balance + tx.value() as u64);
-            return Ok(());
+        if tx.is_coinbase() {
+            return self.mint(tx.to(), tx.value());
         }
 
-        if tx.value() > self.balances[tx.from()] as i64 {
-            let message = format!(
-                "Wrong TX. Sender {} balance is {} TBB. Tx cost is {} TBB",
-                tx.from(),
-                self.balances[tx.from()],
-                tx.value(),
-            );
-            return Err(message);
+        let expected = self.next_nonce(tx.from());
+        if tx.nonce() != expected {
+            return Err(DatabaseError::InvalidNonce {
+                account: tx.from().clone(),
+                expected,
+                found: tx.nonce(),
+            });
         }
-        let from_balance = from_balance - tx.value() as u64;
-        let to_balance = to_balance + tx.value() as u64;
-        self.balances
-            .insert(tx.from().clone(), from_balance - tx.value() as u64);
-        self.balances
-            .insert(tx.to().clone(), to_balance + tx.value() as u64);
+        let cost = tx.cost().ok_or_else(|| DatabaseError::Overflow {
+            account: tx.from().clone(),
+        })?;
+        self.debit(tx.from(), cost)?;
+        self.nonces.insert(tx.from().clone(), tx.nonce());
+        self.credit(tx.to(), tx.value())?;
+        if tx.fee().is_zero() {
+            return Ok(());
+        }
+        if miner.is_empty() || self.consensus.fees.burn_fees {
+            self.supply.burned = self
+                .supply
+                .burned
+                .checked_add(tx.fee())
+                .ok_or(DatabaseError::SupplyOverflow)?;
+            return Ok(());
+        }
+        self.credit(miner, tx.fee())
+    }
+    // Txs from before the migration have no nonce, fee or chain id, so they
+    // only move balances. Block rewards were transfers marked with a "reward"
+    // data string that credited the recipient without debiting the sender.
+    fn apply_legacy_tx(&mut self, tx: &Tx) -> Result<(), DatabaseError> {
+        println!("Applying legacy tx {:?}", tx);
+        if tx.data() == LEGACY_REWARD_DATA {
+            return self.mint(tx.to(), tx.value());
+        }
+        self.debit(tx.from(), tx.value())?;
+        self.credit(tx.to(), tx.value())
+    }
+    fn mint(&mut self, account: &Account, amount: Amount) -> Result<(), DatabaseError> {
+        self.supply.minted = self
+            .supply
+            .minted
+            .checked_add(amount)
+            .ok_or(DatabaseError::SupplyOverflow)?;
+        self.credit(account, amount)
+    }
+    fn debit(&mut self, account: &Account, cost: Amount) -> Result<(), DatabaseError> {
+        let balance = *self.balances.get(account).unwrap_or(&Amount::ZERO);
+        let Some(balance) = balance.checked_sub(cost) else {
+            return Err(DatabaseError::InsufficientBalance {
+                account: account.clone(),
+                balance,
+                cost,
+            });
+        };
+        self.balances.insert(account.clone(), balance);
         Ok(())
     }
+    fn credit(&mut self, account: &Account, amount: Amount) -> Result<(), DatabaseError> {
+        let balance = *self.balances.get(account).unwrap_or(&Amount::ZERO);
+        let balance = balance
+            .checked_add(amount)
+            .ok_or_else(|| DatabaseError::Overflow {
+                account: account.clone(),
+            })?;
+        self.balances.insert(account.clone(), balance);
+        Ok(())
+    }
+}
+
+const LEGACY_REWARD_DATA: &str = "reward";
+
+fn next_nonce(nonces: &HashMap<Account, u64>, account: &Account) -> u64 {
+    nonces.get(account).unwrap_or(&0) + 1
+}
+
+fn unix_now() -> Result<u64, DatabaseError> {
+    Ok(time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map_err(|err| DatabaseError::Io(err.to_string()))?
+        .as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        dev_accounts, dev_chain_spec, get_blocks_db_file_path, get_config_file_path,
+        get_database_dir_path, get_snapshots_dir_path, list_snapshots, migrate_legacy_data_dir,
+        open_data_dir_stores, read_stored_block, verify_chain, BlockLimits, DevAccount,
+        StorageBackend, StorageConfig, StoredBlock, DEV_CHAIN_ID,
+    };
+    use proptest::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const ACCOUNTS: [&str; 4] = ["andrej", "babayaga", "caesar", "cerf"];
+    const MINER: &str = "miner";
+    const CHAIN_ID: &str = "the-blockchain-bar-ledger";
+
+    static DATA_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    fn new_data_dir() -> String {
+        let path = std::env::temp_dir().join(format!(
+            "tbb-state-test-{}-{}",
+            std::process::id(),
+            DATA_DIR_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let data_dir = path.to_str().unwrap().to_string();
+        std::fs::create_dir_all(get_database_dir_path(&data_dir).unwrap()).unwrap();
+        std::fs::write(
+            get_genesis_json_file_path(&data_dir).unwrap(),
+            r#"{
+                "genesis_time": "2019-03-18T00:00:00.000000000Z",
+                "chain_id": "the-blockchain-bar-ledger",
+                "balances": { "andrej": 1000000, "cerf": 500000 }
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(get_blocks_db_file_path(&data_dir).unwrap(), "").unwrap();
+        data_dir
+    }
+
+    fn new_legacy_data_dir(blocks: usize) -> String {
+        let data_dir = new_data_dir();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+        let root = std::path::Path::new(&data_dir);
+        std::fs::create_dir_all(root).unwrap();
+        std::fs::write(root.join("genesis.json"), include_str!("genesis.json")).unwrap();
+        let block_db: Vec<&str> = include_str!("block.db").lines().take(blocks).collect();
+        std::fs::write(root.join("block.db"), block_db.join("\n") + "\n").unwrap();
+        data_dir
+    }
+
+    #[derive(Debug, Clone)]
+    struct TxSpec {
+        from: usize,
+        to: usize,
+        value: u64,
+        fee: u64,
+        coinbase: bool,
+        nonce_offset: i64,
+    }
+
+    impl TxSpec {
+        fn from(&self) -> Account {
+            ACCOUNTS[self.from].to_string()
+        }
+        fn build(&self, next_nonce: u64) -> Tx {
+            if self.coinbase {
+                return Tx::coinbase(
+                    CHAIN_ID,
+                    ACCOUNTS[self.to].to_string(),
+                    Amount::from(self.value),
+                    0,
+                );
+            }
+            let nonce = (next_nonce as i64 + self.nonce_offset).max(0) as u64;
+            Tx::new(
+                CHAIN_ID,
+                self.from(),
+                ACCOUNTS[self.to].to_string(),
+                Amount::from(self.value),
+                Amount::from(self.fee),
+                nonce,
+                "",
+            )
+        }
+    }
+
+    fn tx_strategy() -> impl Strategy<Value = TxSpec> {
+        (
+            0..ACCOUNTS.len(),
+            0..ACCOUNTS.len(),
+            0u64..1_500_000,
+            0u64..100,
+            prop::bool::weighted(0.1),
+            prop_oneof![8 => Just(0i64), 1 => Just(-1i64), 1 => Just(1i64)],
+        )
+            .prop_map(|(from, to, value, fee, coinbase, nonce_offset)| TxSpec {
+                from,
+                to,
+                value,
+                fee,
+                coinbase,
+                nonce_offset,
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn apply_tx_conserves_supply(txs in prop::collection::vec(tx_strategy(), 1..50)) {
+            let data_dir = new_data_dir();
+            let mut state = State::new_state_from_disk(&data_dir).unwrap();
+
+            let miner = MINER.to_string();
+            for spec in txs {
+                let tx = spec.build(state.next_nonce(&spec.from()));
+                let balances_before = state.get_balances().clone();
+                let supply_before = state.supply().total().unwrap();
+                let result = state.apply_tx(&tx, &miner);
+                prop_assert!(state.check_supply_invariant().is_ok());
+
+                let balance_of = |balances: &HashMap<Account, Amount>, account: &Account| {
+                    *balances.get(account).unwrap_or(&Amount::ZERO)
+                };
+                let mut expected = balances_before.clone();
+                let mut expected_supply = supply_before;
+                match result {
+                    Err(_) => {}
+                    Ok(()) if tx.is_coinbase() => {
+                        let to_balance = balance_of(&expected, tx.to());
+                        expected.insert(tx.to().clone(), to_balance.checked_add(tx.value()).unwrap());
+                        expected_supply = supply_before.checked_add(tx.value()).unwrap();
+                    }
+                    Ok(()) => {
+                        let from_balance = balance_of(&expected, tx.from());
+                        expected.insert(tx.from().clone(), from_balance.checked_sub(tx.cost().unwrap()).unwrap());
+                        let to_balance = balance_of(&expected, tx.to());
+                        expected.insert(tx.to().clone(), to_balance.checked_add(tx.value()).unwrap());
+                        if !tx.fee().is_zero() {
+                            let miner_balance = balance_of(&expected, &miner);
+                            expected.insert(miner.clone(), miner_balance.checked_add(tx.fee()).unwrap());
+                        }
+                    }
+                }
+                prop_assert_eq!(state.get_balances(), &expected);
+                prop_assert_eq!(state.supply().total().unwrap(), expected_supply);
+            }
+            std::fs::remove_dir_all(&data_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn snapshots_match_full_replay() {
+        let data_dir = new_data_dir();
+        let config = Config {
+            snapshots: SnapshotConfig {
+                interval: 2,
+                keep: 2,
+            },
+            ..Config::default()
+        };
+        config
+            .write(&get_config_file_path(&data_dir).unwrap())
+            .unwrap();
+        let mut state = State::new_state_from_disk(&data_dir).unwrap();
+        state.set_miner(MINER.to_string());
+        for nonce in 1..=5 {
+            let spec = TxSpec {
+                from: 0,
+                to: 2,
+                value: 10,
+                fee: 1,
+                coinbase: false,
+                nonce_offset: 0,
+            };
+            state.add_tx(&spec.build(nonce)).unwrap();
+            state.persist().unwrap();
+        }
+
+        let snapshot_dir = get_snapshots_dir_path(&data_dir).unwrap();
+        let snapshots = list_snapshots(&snapshot_dir).unwrap();
+        let heights: Vec<u64> = snapshots.iter().map(|(height, _)| *height).collect();
+        assert_eq!(heights, vec![4, 2]);
+
+        let reloaded = State::new_read_only_state_from_disk(&data_dir).unwrap();
+        assert_eq!(reloaded.get_balances(), state.get_balances());
+        assert_eq!(reloaded.supply(), state.supply());
+        assert_eq!(reloaded.latest_block_hash(), state.latest_block_hash());
+        assert_eq!(reloaded.next_nonce(&ACCOUNTS[0].to_string()), 6);
+
+        let mut forged = Snapshot::load(&snapshots[0].1).unwrap();
+        forged
+            .balances
+            .insert(ACCOUNTS[1].to_string(), Amount::from(1u64));
+        forged.write(&snapshots[0].1).unwrap();
+        let reloaded = State::new_read_only_state_from_disk(&data_dir).unwrap();
+        assert_eq!(reloaded.get_balances(), state.get_balances());
+        assert_eq!(reloaded.supply(), state.supply());
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn storage_backends_agree() {
+        let mut results = Vec::new();
+        for backend in [
+            StorageBackend::FlatFile,
+            StorageBackend::Kv,
+            StorageBackend::Memory,
+        ] {
+            let data_dir = new_data_dir();
+            let config = Config {
+                snapshots: SnapshotConfig {
+                    interval: 2,
+                    keep: 2,
+                },
+                storage: StorageConfig { backend },
+                ..Config::default()
+            };
+            config
+                .write(&get_config_file_path(&data_dir).unwrap())
+                .unwrap();
+            let lock = Arc::new(DataDirLock::acquire(&data_dir).unwrap());
+            let (config, blocks, store) = open_data_dir_stores(&data_dir, true).unwrap();
+            let mut state = State::load(
+                &data_dir,
+                config.clone(),
+                blocks.clone(),
+                store.clone(),
+                Some(lock),
+                None,
+            )
+            .unwrap();
+            state.set_miner(MINER.to_string());
+            for nonce in 1..=5 {
+                let spec = TxSpec {
+                    from: 0,
+                    to: 2,
+                    value: 10,
+                    fee: 1,
+                    coinbase: false,
+                    nonce_offset: 0,
+                };
+                state.add_tx(&spec.build(nonce)).unwrap();
+                state.persist().unwrap();
+            }
+            assert_eq!(store.lock().unwrap().snapshots().unwrap(), vec![4, 2]);
+
+            let report = prune_chain(&data_dir, blocks.clone(), store.clone(), 2)
+                .unwrap()
+                .unwrap();
+            assert_eq!((report.anchor, report.pruned, report.kept), (2, 3, 2));
+            let stored = |number| {
+                read_stored_block(&*blocks.lock().unwrap(), &*store.lock().unwrap(), number)
+            };
+            assert!(matches!(
+                stored(1).unwrap(),
+                StoredBlock::Pruned {
+                    header: Some(_),
+                    ..
+                }
+            ));
+            assert!(matches!(stored(3).unwrap(), StoredBlock::Full(_)));
+            assert_eq!(stored(5).unwrap_err(), DatabaseError::BlockNotFound(5));
+
+            let reloaded = State::load(&data_dir, config, blocks, store, None, None).unwrap();
+            assert_eq!(reloaded.get_balances(), state.get_balances());
+            assert_eq!(reloaded.supply(), state.supply());
+            assert_eq!(reloaded.latest_block_hash(), state.latest_block_hash());
+            results.push((state.get_balances().clone(), *state.supply()));
+            drop(state);
+            drop(reloaded);
+            std::fs::remove_dir_all(&data_dir).unwrap();
+        }
+        assert!(results.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test]
+    fn empty_chain_id_is_only_accepted_for_migrated_history() {
+        let data_dir = new_data_dir();
+        let mut state = State::new_state_from_disk(&data_dir).unwrap();
+        let chainless = Block::new("", [0; 32], 1, vec![], 0, Account::new());
+        assert!(matches!(
+            state.add_block(chainless).unwrap_err(),
+            DatabaseError::WrongChain { .. }
+        ));
+        drop(state);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        let data_dir = new_data_dir();
+        let root = std::path::Path::new(&data_dir);
+        std::fs::rename(
+            get_genesis_json_file_path(&data_dir).unwrap(),
+            root.join("genesis.json"),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(get_database_dir_path(&data_dir).unwrap()).unwrap();
+        let mut legacy_blocks = String::new();
+        let mut parent = [0; 32];
+        for number in 0..2 {
+            let block = Block::new("", parent, number + 1, vec![], number, Account::new());
+            let block_fs = BlockFS {
+                key: block.hash().unwrap(),
+                value: block,
+            };
+            parent = block_fs.key;
+            legacy_blocks += &serde_json::to_string(&block_fs).unwrap();
+            legacy_blocks += "\n";
+        }
+        std::fs::write(root.join("block.db"), legacy_blocks).unwrap();
+
+        let mut state = State::new_state_from_disk(&data_dir).unwrap();
+        assert_eq!(read_legacy_height(&data_dir).unwrap(), Some(1));
+        assert_eq!(state.latest_block_hash(), parent);
+        let chainless = Block::new("", parent, 3, vec![], 2, Account::new());
+        assert!(matches!(
+            state.add_block(chainless).unwrap_err(),
+            DatabaseError::WrongChain { .. }
+        ));
+        let block = Block::new(CHAIN_ID, parent, 3, vec![], 2, Account::new());
+        state.add_block(block).unwrap();
+        drop(state);
+
+        let report = verify_chain(&data_dir).unwrap();
+        assert!(report.error.is_none(), "{:?}", report.error);
+        assert_eq!(report.blocks, 3);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn legacy_blocks_replay_without_nonces() {
+        let data_dir = new_legacy_data_dir(4);
+        let mut state = State::new_state_from_disk(&data_dir).unwrap();
+        assert_eq!(state.next_block_number(), 4);
+        assert_eq!(state.next_nonce(&"andrej".to_string()), 1);
+
+        let tx = Tx::new(
+            CHAIN_ID,
+            "andrej".to_string(),
+            "cerf".to_string(),
+            Amount::from(5u64),
+            Amount::from(1u64),
+            1,
+            "",
+        );
+        state.add_tx(&tx).unwrap();
+        state.persist().unwrap();
+        assert_eq!(state.next_nonce(&"andrej".to_string()), 2);
+        drop(state);
+
+        let reloaded = State::new_read_only_state_from_disk(&data_dir).unwrap();
+        assert_eq!(reloaded.next_block_number(), 5);
+        assert_eq!(reloaded.next_nonce(&"andrej".to_string()), 2);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn migrated_legacy_datadir_loads_with_its_rewards() {
+        let data_dir = new_legacy_data_dir(4);
+        migrate_legacy_data_dir(&data_dir).unwrap();
+        assert_eq!(read_legacy_height(&data_dir).unwrap(), Some(3));
+
+        let (config, blocks, store) = open_data_dir_stores(&data_dir, false).unwrap();
+        let state = State::load(&data_dir, config, blocks, store, None, None).unwrap();
+        assert_eq!(state.next_block_number(), 4);
+        let balances = state.get_balances();
+        assert_eq!(balances["andrej"], Amount::from(999_800u64));
+        assert_eq!(balances["babayaga"], Amount::from(400u64));
+        assert_eq!(state.supply().minted, Amount::from(200u64));
+        state.check_supply_invariant().unwrap();
+
+        let report = verify_chain(&data_dir).unwrap();
+        assert!(report.error.is_none(), "{:?}", report.error);
+        assert_eq!(report.blocks, 4);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn kv_datadir_reports_a_running_writer_to_readers() {
+        let data_dir = new_data_dir();
+        let config = Config {
+            storage: StorageConfig {
+                backend: StorageBackend::Kv,
+            },
+            ..Config::default()
+        };
+        config
+            .write(&get_config_file_path(&data_dir).unwrap())
+            .unwrap();
+        let mut state = State::new_state_from_disk(&data_dir).unwrap();
+        state.persist().unwrap();
+
+        assert_eq!(
+            State::new_read_only_state_from_disk(&data_dir).unwrap_err(),
+            DatabaseError::DataDirLocked {
+                data_dir: data_dir.clone(),
+                pid: Some(std::process::id()),
+            }
+        );
+        drop(state);
+
+        let reader = State::new_read_only_state_from_disk(&data_dir).unwrap();
+        assert_eq!(reader.next_block_number(), 1);
+        drop(reader);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn blocks_need_signatures_from_key_accounts() {
+        let accounts = dev_accounts(2).unwrap();
+        let spec = dev_chain_spec(&accounts, 0).unwrap();
+        let mut state = State::new_in_memory_state(spec, Config::default()).unwrap();
+        let tx = Tx::new(
+            DEV_CHAIN_ID,
+            accounts[0].account.clone(),
+            accounts[1].account.clone(),
+            Amount::from(7u64),
+            Amount::from(1u64),
+            1,
+            "",
+        );
+        let block = |tx: &Tx| {
+            Block::new(
+                DEV_CHAIN_ID,
+                state.latest_block_hash(),
+                1,
+                vec![tx.clone()],
+                0,
+                Account::new(),
+            )
+        };
+
+        let unsigned = block(&tx);
+        let forged = block(
+            &tx.clone().with_signature(
+                &accounts[1]
+                    .signing_key()
+                    .unwrap()
+                    .sign_hash(&tx.hash().unwrap()),
+            ),
+        );
+        let signature = accounts[0].signing_key().unwrap().sign_tx(&tx).unwrap();
+        let signed = block(&tx.clone().with_signature(&signature));
+        for block in [unsigned, forged] {
+            assert!(matches!(
+                state.add_block(block).unwrap_err(),
+                DatabaseError::BadSignature(_)
+            ));
+        }
+        state.add_block(signed).unwrap();
+        assert_eq!(state.next_nonce(&accounts[0].account), 2);
+    }
+
+    fn signed_transfer(from: &DevAccount, to: &DevAccount, nonce: u64) -> Tx {
+        let tx = Tx::new(
+            DEV_CHAIN_ID,
+            from.account.clone(),
+            to.account.clone(),
+            Amount::from(7u64),
+            Amount::from(1u64),
+            nonce,
+            "",
+        );
+        let signature = from.signing_key().unwrap().sign_tx(&tx).unwrap();
+        tx.with_signature(&signature)
+    }
+
+    #[test]
+    fn blocks_over_the_limits_are_rejected() {
+        let accounts = dev_accounts(2).unwrap();
+        let tx = |nonce| signed_transfer(&accounts[0], &accounts[1], nonce);
+        let block = |parent, txs| Block::new(DEV_CHAIN_ID, parent, 1, txs, 0, Account::new());
+        let mut spec = dev_chain_spec(&accounts, 0).unwrap();
+        spec.limits.max_block_txs = 1;
+        // Parents encode as byte arrays, so a zero parent gives the smallest size.
+        spec.limits.max_block_bytes = block([0; 32], vec![tx(1)]).size().unwrap() - 1;
+        let mut state = State::new_in_memory_state(spec, Config::default()).unwrap();
+        let parent = state.latest_block_hash();
+
+        assert!(matches!(
+            state
+                .add_block(block(parent, vec![tx(1), tx(2)]))
+                .unwrap_err(),
+            DatabaseError::TooManyTxs { max: 1, found: 2 }
+        ));
+        assert!(matches!(
+            state.add_block(block(parent, vec![tx(1)])).unwrap_err(),
+            DatabaseError::BlockTooLarge { .. }
+        ));
+        assert_eq!(state.next_block_number(), 0);
+    }
+
+    #[test]
+    fn persist_stops_filling_blocks_at_the_limits() {
+        let accounts = dev_accounts(2).unwrap();
+        let miner = accounts[0].account.clone();
+        let tx = |nonce| signed_transfer(&accounts[0], &accounts[1], nonce);
+        let fill = |limits: BlockLimits| {
+            let mut spec = dev_chain_spec(&accounts, 0).unwrap();
+            spec.limits = limits;
+            let mut state = State::new_in_memory_state(spec, Config::default()).unwrap();
+            state.set_miner(miner.clone());
+            for nonce in 1..=4 {
+                state.add_tx(&tx(nonce)).unwrap();
+            }
+            state.persist().unwrap();
+            let (blocks, _) = state.stores();
+            let sealed = blocks.lock().unwrap().get(0).unwrap().unwrap();
+            (sealed.value.txs().len(), state.mempool().len())
+        };
+
+        // The coinbase counts towards max_block_txs.
+        assert_eq!(
+            fill(BlockLimits {
+                max_block_txs: 3,
+                ..BlockLimits::default()
+            }),
+            (3, 2)
+        );
+        let coinbase = Tx::coinbase(DEV_CHAIN_ID, miner.clone(), Amount::from(100u64), 0);
+        // Parents encode as byte arrays, so this parent gives the largest size.
+        let one_transfer = Block::new(
+            DEV_CHAIN_ID,
+            [u8::MAX; 32],
+            unix_now().unwrap(),
+            vec![coinbase, tx(1)],
+            0,
+            miner.clone(),
+        );
+        let max_block_bytes = one_transfer.size().unwrap();
+        assert_eq!(
+            fill(BlockLimits {
+                max_block_bytes,
+                ..BlockLimits::default()
+            }),
+            (2, 3)
+        );
+    }
+
+    #[test]
+    fn block_rewards_stop_at_the_max_supply() {
+        let accounts = dev_accounts(1).unwrap();
+        let miner = accounts[0].account.clone();
+        let mut spec = dev_chain_spec(&accounts, 0).unwrap();
+        let genesis_supply = spec.validate().unwrap();
+        spec.rewards.max_supply = genesis_supply.checked_add(Amount::from(150u64)).unwrap();
+        let mut state = State::new_in_memory_state(spec, Config::default()).unwrap();
+        state.set_miner(miner.clone());
+
+        for (block_number, reward) in [(0, 100u64), (1, 50), (2, 0)] {
+            assert_eq!(state.block_reward(block_number), Amount::from(reward));
+            state.persist().unwrap();
+        }
+        assert_eq!(state.next_halving_height(3), None);
+        assert_eq!(state.supply().minted, Amount::from(150u64));
+        assert_eq!(
+            state.get_balances()[&miner],
+            genesis_supply.checked_add(Amount::from(150u64)).unwrap()
+        );
+        let (blocks, _) = state.stores();
+        let last = blocks.lock().unwrap().get(2).unwrap().unwrap();
+        assert!(last.value.txs().is_empty());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+        #[test]
+        fn persisted_blocks_conserve_supply(
+            txs in prop::collection::vec(tx_strategy(), 1..30),
+            seal_every in 1usize..5,
+        ) {
+            let data_dir = new_data_dir();
+            let mut state = State::new_state_from_disk(&data_dir).unwrap();
+            state.set_miner(MINER.to_string());
+
+            for (i, spec) in txs.iter().enumerate() {
+                let _ = state.add_tx(&spec.build(state.next_pending_nonce(&spec.from())));
+                if (i + 1) % seal_every == 0 {
+                    state.persist().unwrap();
+                    prop_assert!(state.check_supply_invariant().is_ok());
+                }
+            }
+            state.persist().unwrap();
+            prop_assert!(state.check_supply_invariant().is_ok());
+
+            let reloaded = State::new_read_only_state_from_disk(&data_dir).unwrap();
+            prop_assert_eq!(reloaded.get_balances(), state.get_balances());
+            prop_assert_eq!(reloaded.supply(), state.supply());
+            prop_assert!(reloaded.check_supply_invariant().is_ok());
+            std::fs::remove_dir_all(&data_dir).unwrap();
+        }
+    }
 }
@@ -0,0 +1,148 @@
+use std::sync::{Arc, Mutex};
+
+use super::{
+    file_exists, get_blocks_db_file_path, get_config_file_path, get_kv_store_file_path, BlockFS,
+    Config, DatabaseError, FlatFileBlockStore, FlatFileStateStore, HeaderFS, KvStore,
+    MemoryBlockStore, MemoryStateStore, Snapshot, SnapshotArchive,
+};
+
+pub(crate) type BlockIter = Box<dyn Iterator<Item = Result<BlockFS, DatabaseError>> + Send>;
+pub(crate) type SharedBlockStore = Arc<Mutex<dyn BlockStore>>;
+pub(crate) type SharedStateStore = Arc<Mutex<dyn StateStore>>;
+
+pub(crate) trait BlockStore: Send + std::fmt::Debug {
+    fn append(&mut self, block_fs: &BlockFS) -> Result<(), DatabaseError>;
+    fn iter(&self) -> Result<BlockIter, DatabaseError>;
+    fn sync(&mut self) -> Result<(), DatabaseError>;
+    fn truncate(&mut self, len: u64) -> Result<Option<String>, DatabaseError>;
+    fn retain_after(&mut self, block_number: u64) -> Result<(), DatabaseError>;
+    fn add_headers(&mut self, headers: &[HeaderFS]) -> Result<(), DatabaseError>;
+    fn header(&self, block_number: u64) -> Result<Option<HeaderFS>, DatabaseError>;
+
+    fn get(&self, block_number: u64) -> Result<Option<BlockFS>, DatabaseError> {
+        for block_fs in self.iter()? {
+            let block_fs = block_fs?;
+            if block_fs.value.header().number() == block_number {
+                return Ok(Some(block_fs));
+            }
+        }
+        Ok(None)
+    }
+}
+
+pub(crate) trait StateStore: Send + std::fmt::Debug {
+    fn snapshots(&self) -> Result<Vec<u64>, DatabaseError>;
+    fn snapshot(&self, block_number: u64) -> Result<Snapshot, DatabaseError>;
+    fn write_snapshot(&mut self, snapshot: &Snapshot, keep: usize) -> Result<(), DatabaseError>;
+    fn remove_snapshots_until(&mut self, block_number: u64) -> Result<(), DatabaseError>;
+    fn anchor(&self) -> Result<Option<SnapshotArchive>, DatabaseError>;
+    fn set_anchor(&mut self, archive: &SnapshotArchive) -> Result<(), DatabaseError>;
+}
+
+/// Where a datadir keeps its blocks and snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// JSON lines in `database/block.db`.
+    #[default]
+    FlatFile,
+    /// Nowhere, the chain is lost on exit.
+    Memory,
+    /// An embedded key-value store in `database/chain.redb`.
+    Kv,
+}
+
+impl std::str::FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat_file" => Ok(StorageBackend::FlatFile),
+            "memory" => Ok(StorageBackend::Memory),
+            "kv" => Ok(StorageBackend::Kv),
+            _ => Err(format!(
+                "unknown storage backend {:?}, use flat_file, memory or kv",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageBackend::FlatFile => write!(f, "flat_file"),
+            StorageBackend::Memory => write!(f, "memory"),
+            StorageBackend::Kv => write!(f, "kv"),
+        }
+    }
+}
+
+/// The `storage` section of the datadir config. The backend is fixed when
+/// the datadir is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+}
+
+pub(crate) fn memory_stores() -> (SharedBlockStore, SharedStateStore) {
+    (
+        Arc::new(Mutex::new(MemoryBlockStore::default())),
+        Arc::new(Mutex::new(MemoryStateStore::default())),
+    )
+}
+
+pub(crate) fn open_stores(
+    data_dir: &str,
+    storage: &StorageConfig,
+    writable: bool,
+) -> Result<(SharedBlockStore, SharedStateStore), DatabaseError> {
+    let blocks_db_path = get_blocks_db_file_path(data_dir)?;
+    let kv_path = get_kv_store_file_path(data_dir)?;
+    let has_flat_blocks = std::fs::metadata(&blocks_db_path)
+        .map(|metadata| metadata.len() > 0)
+        .unwrap_or(false);
+    let other = match storage.backend {
+        StorageBackend::FlatFile if file_exists(&kv_path) => Some(StorageBackend::Kv),
+        StorageBackend::Kv | StorageBackend::Memory if has_flat_blocks => {
+            Some(StorageBackend::FlatFile)
+        }
+        _ => None,
+    };
+    if let Some(other) = other {
+        return Err(DatabaseError::Corrupt(format!(
+            "{} holds a {} chain but storage.backend is {}, move it with `tbb chain export` and `tbb chain import`",
+            data_dir, other, storage.backend
+        )));
+    }
+
+    match storage.backend {
+        StorageBackend::FlatFile => Ok((
+            Arc::new(Mutex::new(FlatFileBlockStore::open(data_dir, writable)?)),
+            Arc::new(Mutex::new(FlatFileStateStore::open(data_dir)?)),
+        )),
+        StorageBackend::Memory => Ok(memory_stores()),
+        StorageBackend::Kv => {
+            let store = KvStore::open(data_dir, writable)?;
+            Ok((
+                Arc::new(Mutex::new(store.clone())),
+                Arc::new(Mutex::new(store)),
+            ))
+        }
+    }
+}
+
+pub(crate) fn open_data_dir_stores(
+    data_dir: &str,
+    writable: bool,
+) -> Result<(Config, SharedBlockStore, SharedStateStore), DatabaseError> {
+    let config_path = get_config_file_path(data_dir)?;
+    let config = if !writable && !file_exists(&config_path) {
+        Config::default()
+    } else {
+        Config::load(&config_path)?
+    };
+    let (blocks, store) = open_stores(data_dir, &config.storage, writable)?;
+    Ok((config, blocks, store))
+}
@@ -0,0 +1,23 @@
+use super::Amount;
+
+/// Token totals: the sum of all balances is always
+/// `genesis + minted - burned`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Supply {
+    pub genesis: Amount,
+    pub minted: Amount,
+    pub burned: Amount,
+}
+
+impl Supply {
+    /// `genesis + minted`, or `None` on overflow.
+    pub fn issued(&self) -> Option<Amount> {
+        self.genesis.checked_add(self.minted)
+    }
+    /// `genesis + minted - burned`, or `None` on overflow.
+    pub fn total(&self) -> Option<Amount> {
+        self.genesis
+            .checked_add(self.minted)?
+            .checked_sub(self.burned)
+    }
+}
@@ -1,41 +1,173 @@
 use serde::{Deserialize, Serialize};
 
+use super::{Amount, DatabaseError, Hash};
+
+/// An account address: a hex ed25519 public key, or a plain name on
+/// chains that predate signatures.
 pub type Account = String;
 
+/// Turns `value` into an [`Account`] without validating it.
 pub fn new_account(value: &str) -> Account {
     value.to_string()
 }
 
+/// What a [`Tx`] does to balances.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TxKind {
+    /// Moves `value` from `from` to `to` and pays `fee` to the miner.
+    #[default]
+    Transfer,
+    /// Mints the block reward to `to`. Only valid as the first tx of a block.
+    Coinbase,
+}
+
+impl TxKind {
+    pub fn is_transfer(&self) -> bool {
+        *self == TxKind::Transfer
+    }
+}
+
+/// A transfer of `value` plus `fee` from one account to another.
+///
+/// Txs from an account apply in nonce order starting at 1. A tx from a key
+/// account must carry a signature of its [`Tx::hash`] made with that key.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Tx {
+    #[serde(default, skip_serializing_if = "TxKind::is_transfer")]
+    kind: TxKind,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    chain_id: String,
     from: Account,
     to: Account,
-    value: i64,
+    value: Amount,
+    #[serde(default, skip_serializing_if = "Amount::is_zero")]
+    fee: Amount,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    nonce: u64,
     data: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    signature: String,
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
 }
 
 impl Tx {
-    pub fn new(from: Account, to: Account, value: &u64, data: &str) -> Tx {
+    /// An unsigned transfer. Sign it with
+    /// [`SigningKey::sign_tx`](crate::SigningKey::sign_tx) and attach the result with
+    /// [`Tx::with_signature`].
+    pub fn new(
+        chain_id: &str,
+        from: Account,
+        to: Account,
+        value: Amount,
+        fee: Amount,
+        nonce: u64,
+        data: &str,
+    ) -> Tx {
         Tx {
+            kind: TxKind::Transfer,
+            chain_id: chain_id.to_string(),
             from,
             to,
-            value: *value as i64,
+            value,
+            fee,
+            nonce,
             data: data.to_string(),
+            signature: String::new(),
+        }
+    }
+    /// Attaches a hex ed25519 signature of [`Tx::hash`].
+    pub fn with_signature(mut self, signature: &str) -> Tx {
+        self.signature = signature.to_string();
+        self
+    }
+    /// The reward tx minting `value` to `to` in block `block_number`.
+    pub fn coinbase(chain_id: &str, to: Account, value: Amount, block_number: u64) -> Tx {
+        Tx {
+            kind: TxKind::Coinbase,
+            chain_id: chain_id.to_string(),
+            from: Account::new(),
+            to,
+            value,
+            fee: Amount::ZERO,
+            nonce: block_number,
+            data: String::new(),
+            signature: String::new(),
         }
     }
-    pub fn is_reward(&self) -> bool {
-        self.data == "reward"
+    /// Whether the tx is a transfer or a coinbase.
+    pub fn kind(&self) -> TxKind {
+        self.kind
+    }
+    /// True for block reward txs.
+    pub fn is_coinbase(&self) -> bool {
+        self.kind == TxKind::Coinbase
     }
+    /// Chain the tx is valid on, so it cannot be replayed on another chain.
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+    /// Sender, empty for coinbase txs.
     pub fn from(&self) -> &Account {
         &self.from
     }
+    /// Recipient.
     pub fn to(&self) -> &Account {
         &self.to
     }
-    pub fn value(&self) -> i64 {
+    /// Amount moved to the recipient.
+    pub fn value(&self) -> Amount {
         self.value
     }
-    pub fn data(&self) -> &String {
+    /// Amount paid to the miner, or burned if the chain burns fees.
+    pub fn fee(&self) -> Amount {
+        self.fee
+    }
+    /// Position of the tx among the sender's txs, starting at 1. Coinbase
+    /// txs use the block number, and txs from migrated legacy blocks have 0.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+    /// Free-form payload, at most `limits.max_tx_data_bytes` long.
+    pub fn data(&self) -> &str {
         &self.data
     }
+    /// The hex signature, if the tx is signed.
+    pub fn signature(&self) -> Option<&str> {
+        match self.signature.as_str() {
+            "" => None,
+            signature => Some(signature),
+        }
+    }
+    /// SHA-256 of the tx without its signature. This is what signers sign
+    /// and what identifies the tx in the mempool and the API.
+    pub fn hash(&self) -> Result<Hash, DatabaseError> {
+        let unsigned;
+        let tx = match self.signature() {
+            Some(_) => {
+                unsigned = self.clone().with_signature("");
+                &unsigned
+            }
+            None => self,
+        };
+        let tx_json = serde_json::to_string(tx)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        let hash = ring::digest::digest(&ring::digest::SHA256, tx_json.as_bytes());
+        let mut result = [0u8; 32];
+        result.copy_from_slice(hash.as_ref());
+        Ok(result)
+    }
+    /// Size of the tx's JSON encoding in bytes, signature included.
+    pub fn size(&self) -> Result<usize, DatabaseError> {
+        let tx_json = serde_json::to_vec(&self)
+            .map_err(|err| DatabaseError::Serialization(err.to_string()))?;
+        Ok(tx_json.len())
+    }
+    /// `value + fee`, or `None` on overflow.
+    pub fn cost(&self) -> Option<Amount> {
+        self.value.checked_add(self.fee)
+    }
 }
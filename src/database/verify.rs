@@ -0,0 +1,153 @@
+use data_encoding::HEXLOWER;
+
+use super::{
+    migrate_legacy_data_dir, open_data_dir_read_only, open_data_dir_stores, DataDirLock,
+    DatabaseError, Hash, SharedBlockStore, SharedStateStore, State, Supply,
+};
+
+/// Result of [`verify_chain`]. `blocks` counts the valid blocks before
+/// `error`, if there is one.
+#[derive(Debug)]
+pub struct ChainReport {
+    pub blocks: u64,
+    pub anchor: Option<u64>,
+    pub genesis_hash: Hash,
+    pub latest_block_hash: Hash,
+    pub supply: Supply,
+    pub error: Option<VerifyError>,
+}
+
+/// Result of [`repair_chain`]. Dropped blocks are moved to `backup_path`
+/// when the backend supports it.
+#[derive(Debug)]
+pub struct RepairReport {
+    pub kept: u64,
+    pub dropped: u64,
+    pub backup_path: Option<String>,
+    pub error: Option<VerifyError>,
+}
+
+/// The first invalid block: its height, stored hash and what is wrong.
+#[derive(Debug)]
+pub struct VerifyError {
+    pub index: u64,
+    pub key: Option<Hash>,
+    pub error: DatabaseError,
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.key {
+            Some(key) => write!(
+                f,
+                "block #{} ({}): {}",
+                self.index,
+                HEXLOWER.encode(&key),
+                self.error
+            ),
+            None => write!(f, "block #{}: {}", self.index, self.error),
+        }
+    }
+}
+
+/// Replays every block of `data_dir` without locking it and reports the
+/// first invalid one.
+pub fn verify_chain(data_dir: &str) -> Result<ChainReport, DatabaseError> {
+    open_data_dir_read_only(data_dir)?;
+    let (_, blocks, store) = open_data_dir_stores(data_dir, false)?;
+    verify_stores(data_dir, blocks, store)
+}
+
+fn verify_stores(
+    data_dir: &str,
+    blocks: SharedBlockStore,
+    store: SharedStateStore,
+) -> Result<ChainReport, DatabaseError> {
+    let stored = blocks.lock().unwrap().iter()?;
+    let mut state = State::new_base_state(data_dir, blocks, store)?;
+    let anchor = state.latest_header().as_ref().map(|header| header.number());
+
+    let mut index = 0;
+    let mut applied = false;
+    let mut error = None;
+    for block_fs in stored {
+        let block_fs = match block_fs {
+            Ok(block_fs) => block_fs,
+            Err(err) => {
+                error = Some(VerifyError {
+                    index,
+                    key: None,
+                    error: err,
+                });
+                break;
+            }
+        };
+        if !applied {
+            match state.is_before_base(&block_fs) {
+                Ok(true) => {
+                    index += 1;
+                    continue;
+                }
+                Ok(false) => applied = true,
+                Err(err) => {
+                    error = Some(VerifyError {
+                        index,
+                        key: Some(block_fs.key),
+                        error: err,
+                    });
+                    break;
+                }
+            }
+        }
+        if let Err(err) = state
+            .apply_block_fs(&block_fs)
+            .and_then(|_| state.check_supply_invariant())
+        {
+            error = Some(VerifyError {
+                index,
+                key: Some(block_fs.key),
+                error: err,
+            });
+            break;
+        }
+        index += 1;
+    }
+
+    Ok(ChainReport {
+        blocks: index,
+        anchor,
+        genesis_hash: state.genesis_hash(),
+        latest_block_hash: state.latest_block_hash(),
+        supply: *state.supply(),
+        error,
+    })
+}
+
+/// Truncates `data_dir` to its valid prefix after
+/// [`verify_chain`]-style checks. Needs the datadir lock.
+pub fn repair_chain(data_dir: &str) -> Result<RepairReport, DatabaseError> {
+    let _lock = DataDirLock::acquire(data_dir)?;
+    migrate_legacy_data_dir(data_dir)?;
+    open_data_dir_read_only(data_dir)?;
+    let (_, blocks, store) = open_data_dir_stores(data_dir, true)?;
+    let report = verify_stores(data_dir, blocks.clone(), store)?;
+    if report.error.is_none() {
+        return Ok(RepairReport {
+            kept: report.blocks,
+            dropped: 0,
+            backup_path: None,
+            error: None,
+        });
+    }
+    let mut blocks = blocks.lock().unwrap();
+    let total = blocks.iter()?.count() as u64;
+    let backup_path = blocks.truncate(report.blocks)?;
+    blocks.sync()?;
+
+    Ok(RepairReport {
+        kept: report.blocks,
+        dropped: total - report.blocks,
+        backup_path,
+        error: report.error,
+    })
+}
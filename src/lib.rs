@@ -0,0 +1,17 @@
+//! The TBB ledger as a library.
+//!
+//! [`State`] holds the ledger and validates [`Tx`]s and [`Block`]s against the
+//! rules of its [`Genesis`]. [`NodeBuilder`] serves a state over HTTP and
+//! [`Client`] talks to such a node. Datadir maintenance such as export,
+//! import, pruning and repair lives in [`database`].
+
+pub mod client;
+pub mod database;
+pub mod node;
+
+pub use client::{BlockingClient, Client, ClientBuilder, ClientError};
+pub use database::{
+    Account, Amount, Block, BlockHeader, ChainSpec, Config, DatabaseError, Genesis, Hash,
+    SigningKey, State, StorageBackend, Tx,
+};
+pub use node::{NodeBuilder, RunningNode, SealMode};
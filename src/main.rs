@@ -1,6 +1,4 @@
 mod cmd;
-mod database;
-mod node;
 
 fn main() {
     cmd::run_cmd();
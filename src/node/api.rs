@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use data_encoding::HEXLOWER;
+
+use crate::database::{
+    Account, Amount, Block, BlockHeader, DatabaseError, FeeBucket, HeaderFS, PendingTx, SigningKey,
+    Tx, TxKind,
+};
+
+/// Response of `GET /balances/list`: every balance as of block `hash`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BalanceRes {
+    pub balances: HashMap<String, Amount>,
+    #[serde(rename = "block_hash")]
+    pub hash: String,
+}
+
+/// Response of `GET /accounts/{account}`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct AccountRes {
+    pub account: Account,
+    pub balance: Amount,
+    pub nonce: u64,
+    pub pending_nonce: u64,
+}
+
+/// Body of `POST /tx/add`. The node fills in a missing fee, nonce or chain
+/// id, so a signature must cover the values it ends up with.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct TxAddReq {
+    pub from: String,
+    pub to: String,
+    pub value: Amount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl TxAddReq {
+    /// A request carrying every field of `tx`, signed by `key`. Fails without
+    /// contacting the node unless `key` belongs to the sender.
+    ///
+    /// The node rejects the request if the signature does not match the sender
+    /// and keeps it with the tx, so blocks carrying the tx are checked again.
+    pub fn signed(tx: &Tx, key: &SigningKey) -> Result<TxAddReq, DatabaseError> {
+        Ok(TxAddReq {
+            from: tx.from().clone(),
+            to: tx.to().clone(),
+            value: tx.value(),
+            fee: Some(tx.fee()),
+            nonce: Some(tx.nonce()),
+            data: Some(tx.data().to_string()),
+            chain_id: Some(tx.chain_id().to_string()),
+            signature: Some(key.sign_tx(tx)?),
+        })
+    }
+
+    /// The transaction this request adds, if it pins the nonce, fee and chain
+    /// so that the node cannot fill in anything itself.
+    pub fn tx(&self) -> Option<Tx> {
+        let tx = Tx::new(
+            self.chain_id.as_ref()?,
+            self.from.clone(),
+            self.to.clone(),
+            self.value,
+            self.fee?,
+            self.nonce?,
+            self.data.as_deref().unwrap_or_default(),
+        );
+        match self.signature {
+            Some(ref signature) => Some(tx.with_signature(signature)),
+            None => Some(tx),
+        }
+    }
+}
+
+/// Response of `POST /tx/add`: the hash of the accepted tx.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TxAddRes {
+    #[serde(rename = "tx_hash")]
+    pub hash: String,
+}
+
+/// Response of `GET /node/status`: chain identity, latest block and
+/// issuance.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StatusRes {
+    pub chain_id: String,
+    pub genesis_hash: String,
+    #[serde(rename = "block_hash")]
+    pub hash: String,
+    #[serde(rename = "block_number")]
+    pub number: u64,
+    pub min_fee: Amount,
+    pub circulating_supply: Option<Amount>,
+    pub max_supply: Amount,
+    pub block_reward: Amount,
+    pub next_halving_height: Option<u64>,
+}
+
+/// Paging of `GET /mempool`.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct MempoolQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// Response of `GET /mempool`: a page of pending txs, highest fee first.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MempoolRes {
+    pub total_txs: usize,
+    pub total_bytes: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub txs: Vec<MempoolTxRes>,
+    pub fee_histogram: Vec<FeeBucket>,
+}
+
+/// A pending tx as returned by `GET /mempool` and `GET /mempool/{tx_hash}`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MempoolTxRes {
+    #[serde(rename = "tx_hash")]
+    pub hash: String,
+    pub kind: TxKind,
+    pub from: Account,
+    pub to: Account,
+    pub value: Amount,
+    pub fee: Amount,
+    pub nonce: u64,
+    pub data: String,
+    pub size: usize,
+    pub added_at: u64,
+}
+
+impl From<&PendingTx> for MempoolTxRes {
+    fn from(pending: &PendingTx) -> Self {
+        MempoolTxRes {
+            hash: HEXLOWER.encode(&pending.hash),
+            kind: pending.tx.kind(),
+            from: pending.tx.from().clone(),
+            to: pending.tx.to().clone(),
+            value: pending.tx.value(),
+            fee: pending.tx.fee(),
+            nonce: pending.tx.nonce(),
+            data: pending.tx.data().to_string(),
+            size: pending.size,
+            added_at: pending.added_at,
+        }
+    }
+}
+
+/// Response of `DELETE /mempool/{tx_hash}`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MempoolDropRes {
+    pub dropped: bool,
+    #[serde(rename = "tx_hash")]
+    pub hash: String,
+}
+
+/// Response of `GET /blocks/{number}`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BlockRes {
+    pub block_hash: String,
+    pub block: Block,
+}
+
+/// Response of `GET /blocks/{number}/header`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BlockHeaderRes {
+    pub block_hash: String,
+    pub header: BlockHeader,
+}
+
+impl From<HeaderFS> for BlockHeaderRes {
+    fn from(header_fs: HeaderFS) -> Self {
+        BlockHeaderRes {
+            block_hash: HEXLOWER.encode(&header_fs.key),
+            header: header_fs.header,
+        }
+    }
+}
+
+/// Error response for a block whose body was pruned, with its header if
+/// the node kept it.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PrunedRes {
+    pub error: ErrorBody,
+    #[serde(flatten)]
+    pub header: Option<BlockHeaderRes>,
+}
+
+/// Body of every error response.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ErrorRes {
+    pub error: ErrorBody,
+}
+
+/// A [`DatabaseError::code`](crate::DatabaseError::code) and a readable message.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+}
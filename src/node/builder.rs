@@ -0,0 +1,469 @@
+use std::{
+    net::SocketAddr,
+    sync::{Mutex, MutexGuard},
+};
+
+use actix_web::{dev::ServerHandle, web};
+
+use super::node::{new_server, produce_blocks, Node, HTTP_PORT};
+use crate::database::{
+    dev_accounts, dev_chain_spec, new_account, Account, ChainSpec, Config, DatabaseError, State,
+};
+
+/// How the node turns pending transactions into blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SealMode {
+    /// Seal the mempool every `block_interval_secs` of the chain's consensus config.
+    #[default]
+    Interval,
+    /// Seal a block as soon as `POST /tx/add` accepts a transaction.
+    Instant,
+}
+
+#[derive(Debug, Clone)]
+enum ChainSource {
+    DataDir(String),
+    InMemory(Box<ChainSpec>, Config),
+}
+
+/// Configures and starts a node serving the HTTP API.
+///
+/// A builder starts from a chain source, either [`NodeBuilder::data_dir`] or
+/// [`NodeBuilder::in_memory`]. [`NodeBuilder::run`] serves on the calling
+/// thread until the process is interrupted. [`NodeBuilder::spawn`] serves on a
+/// background thread and returns a [`RunningNode`] to stop it.
+#[derive(Debug, Clone)]
+pub struct NodeBuilder {
+    source: ChainSource,
+    miner: Option<Account>,
+    admin_token: Option<String>,
+    addr: SocketAddr,
+    seal: SealMode,
+    allow_unsigned: bool,
+}
+
+impl NodeBuilder {
+    /// Opens the datadir for writing, creating it from the built-in genesis if
+    /// it is missing. The datadir stays locked until the node stops.
+    pub fn data_dir(data_dir: &str) -> NodeBuilder {
+        NodeBuilder::new(ChainSource::DataDir(data_dir.to_string()))
+    }
+
+    /// Starts a fresh chain from `spec` that lives in memory only and is gone
+    /// when the node stops.
+    pub fn in_memory(spec: ChainSpec, config: Config) -> NodeBuilder {
+        NodeBuilder::new(ChainSource::InMemory(Box::new(spec), config))
+    }
+
+    /// An in-memory chain funding `accounts` deterministic dev accounts (see
+    /// [`dev_accounts`]) that seals a block for every transaction. Fees go to dev
+    /// account 0 unless [`NodeBuilder::miner`] says otherwise.
+    pub fn dev(accounts: usize) -> Result<NodeBuilder, DatabaseError> {
+        let accounts = dev_accounts(accounts)?;
+        let spec = dev_chain_spec(&accounts, 0)?;
+        let mut builder = NodeBuilder::in_memory(spec, Config::default()).seal(SealMode::Instant);
+        builder.miner = accounts.first().map(|dev| dev.account.clone());
+        Ok(builder)
+    }
+
+    fn new(source: ChainSource) -> NodeBuilder {
+        NodeBuilder {
+            source,
+            miner: None,
+            admin_token: None,
+            addr: SocketAddr::from(([127, 0, 0, 1], HTTP_PORT)),
+            seal: SealMode::default(),
+            allow_unsigned: false,
+        }
+    }
+
+    /// Account credited with block rewards and fees. It must be a producer
+    /// under the chain's consensus rules or starting the node fails.
+    pub fn miner(mut self, miner: &str) -> NodeBuilder {
+        self.miner = Some(new_account(miner));
+        self
+    }
+
+    /// Bearer token for admin endpoints such as `DELETE /mempool/{tx_hash}`.
+    /// Without one those endpoints are disabled.
+    pub fn admin_token(mut self, admin_token: &str) -> NodeBuilder {
+        self.admin_token = Some(admin_token.to_string());
+        self
+    }
+
+    /// Address to listen on, `127.0.0.1:8080` by default. Port 0 picks a free
+    /// port, see [`RunningNode::addr`].
+    pub fn bind(mut self, addr: SocketAddr) -> NodeBuilder {
+        self.addr = addr;
+        self
+    }
+
+    /// When to seal pending transactions, [`SealMode::Interval`] by default.
+    pub fn seal(mut self, seal: SealMode) -> NodeBuilder {
+        self.seal = seal;
+        self
+    }
+
+    /// Accept `POST /tx/add` without a signature from accounts that have no
+    /// key, for local development only. Txs from key accounts must be signed
+    /// either way. Off by default.
+    pub fn allow_unsigned(mut self, allow_unsigned: bool) -> NodeBuilder {
+        self.allow_unsigned = allow_unsigned;
+        self
+    }
+
+    /// Loads the chain and checks the miner, without serving anything.
+    pub fn build_state(&self) -> Result<State, DatabaseError> {
+        let mut state = match self.source {
+            ChainSource::DataDir(ref data_dir) => State::new_state_from_disk(data_dir)?,
+            ChainSource::InMemory(ref spec, ref config) => {
+                State::new_in_memory_state(*spec.clone(), config.clone())?
+            }
+        };
+        let miner = self.miner.clone().unwrap_or_default();
+        if !state.consensus().engine.is_producer(&miner) {
+            return Err(DatabaseError::UnauthorizedProducer(miner));
+        }
+        state.set_miner(miner);
+        Ok(state)
+    }
+
+    fn build_node(&self) -> std::io::Result<web::Data<Node>> {
+        let state = self.build_state().map_err(std::io::Error::other)?;
+        Ok(web::Data::new(Node {
+            state: Mutex::new(state),
+            admin_token: self.admin_token.clone(),
+            instant_seal: self.seal == SealMode::Instant,
+            allow_unsigned: self.allow_unsigned,
+        }))
+    }
+
+    /// Serves on the calling thread until the server receives SIGINT or
+    /// SIGTERM. Must not be called from inside an async runtime.
+    pub fn run(self) -> std::io::Result<()> {
+        let node = self.build_node()?;
+        actix_web::rt::System::new().block_on(async move {
+            let (server, addr) = new_server(node.clone(), self.addr)?;
+            println!("Listening on HTTP: {}", addr);
+            actix_web::rt::spawn(produce_blocks(node));
+            server.await
+        })
+    }
+
+    /// Serves on a new thread and returns once the listener is bound.
+    pub fn spawn(self) -> std::io::Result<RunningNode> {
+        let node = self.build_node()?;
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let server_node = node.clone();
+        let addr = self.addr;
+        let thread = std::thread::spawn(move || {
+            actix_web::rt::System::new().block_on(async move {
+                let (server, addr) = match new_server(server_node.clone(), addr) {
+                    Ok(server) => server,
+                    Err(err) => {
+                        let _ = started_tx.send(Err(err));
+                        return Ok(());
+                    }
+                };
+                let _ = started_tx.send(Ok((addr, server.handle())));
+                actix_web::rt::spawn(produce_blocks(server_node));
+                server.await
+            })
+        });
+        let (addr, handle) = match started_rx.recv() {
+            Ok(started) => started?,
+            Err(_) => {
+                return Err(match thread.join() {
+                    Ok(Err(err)) => err,
+                    _ => std::io::Error::other("node thread exited before binding"),
+                })
+            }
+        };
+        Ok(RunningNode {
+            addr,
+            node,
+            handle,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// A node started by [`NodeBuilder::spawn`]. Dropping it stops the node.
+#[derive(Debug)]
+pub struct RunningNode {
+    addr: SocketAddr,
+    node: web::Data<Node>,
+    handle: ServerHandle,
+    thread: Option<std::thread::JoinHandle<std::io::Result<()>>>,
+}
+
+impl RunningNode {
+    /// The address the node is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// `http://` URL of the node's API.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Locks the node's state. Requests and block production wait while the
+    /// guard is held, so keep it short.
+    pub fn state(&self) -> MutexGuard<'_, State> {
+        self.node.state.lock().unwrap()
+    }
+
+    /// Stops accepting connections, finishes in-flight requests and waits for
+    /// the server thread to exit. Idle keep-alive connections from clients
+    /// delay this by up to their 5s timeout.
+    pub fn stop(mut self) -> std::io::Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> std::io::Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        actix_web::rt::System::new().block_on(self.handle.stop(true));
+        thread
+            .join()
+            .map_err(|_| std::io::Error::other("node thread panicked"))?
+    }
+}
+
+impl Drop for RunningNode {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{
+        dev_accounts, get_blocks_db_file_path, get_config_file_path, init_data_dir, Amount,
+        DevAccount, PruningConfig, StorageConfig, Tx, DEV_CHAIN_ID,
+    };
+    use crate::node::{ErrorRes, MempoolRes, MempoolTxRes, PrunedRes, TxAddReq, TxAddRes};
+    use reqwest::StatusCode;
+    use std::io::Write;
+
+    fn http() -> reqwest::blocking::Client {
+        reqwest::blocking::Client::builder()
+            .pool_max_idle_per_host(0)
+            .build()
+            .unwrap()
+    }
+
+    fn transfer(from: &DevAccount, to: &DevAccount, fee: u64, nonce: u64) -> TxAddReq {
+        let tx = Tx::new(
+            DEV_CHAIN_ID,
+            from.account.clone(),
+            to.account.clone(),
+            Amount::from(7u64),
+            Amount::from(fee),
+            nonce,
+            "",
+        );
+        TxAddReq::signed(&tx, &from.signing_key().unwrap()).unwrap()
+    }
+
+    fn spawn_interval_node(accounts: &[DevAccount], admin_token: Option<&str>) -> RunningNode {
+        let spec = dev_chain_spec(accounts, 3600).unwrap();
+        let mut builder = NodeBuilder::in_memory(spec, Config::default())
+            .miner(&accounts[0].account)
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)));
+        if let Some(admin_token) = admin_token {
+            builder = builder.admin_token(admin_token);
+        }
+        builder.spawn().unwrap()
+    }
+
+    #[test]
+    fn spawned_dev_node_seals_every_tx() {
+        let node = NodeBuilder::dev(2)
+            .unwrap()
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .spawn()
+            .unwrap();
+        assert_ne!(node.addr().port(), 0);
+        let accounts = dev_accounts(2).unwrap();
+        let client = http();
+
+        let status: serde_json::Value = client
+            .get(format!("{}/node/status", node.url()))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(status["chain_id"], "tbb-dev");
+
+        let res = client
+            .post(format!("{}/tx/add", node.url()))
+            .json(&serde_json::json!({
+                "from": accounts[0].account,
+                "to": accounts[1].account,
+                "value": 7,
+            }))
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let req = transfer(&accounts[0], &accounts[1], 1, 1);
+        let res = client
+            .post(format!("{}/tx/add", node.url()))
+            .json(&req)
+            .send()
+            .unwrap();
+        assert!(res.status().is_success());
+        {
+            let state = node.state();
+            assert_eq!(state.next_block_number(), 1);
+            assert!(state.mempool().is_empty());
+            let (blocks, _) = state.stores();
+            let sealed = blocks.lock().unwrap().get(0).unwrap().unwrap();
+            let sealed = sealed.value.txs().last().unwrap();
+            assert_eq!(sealed.signature(), req.signature.as_deref());
+            assert_eq!(
+                state.get_balances()[&accounts[1].account],
+                Amount::from(1_000_007u64)
+            );
+        }
+        node.stop().unwrap();
+    }
+
+    #[test]
+    fn mempool_endpoints_list_get_and_drop_pending_txs() {
+        let accounts = dev_accounts(2).unwrap();
+        let node = spawn_interval_node(&accounts, Some("secret"));
+        let client = http();
+        let mut hashes = Vec::new();
+        for (fee, nonce) in [(1, 1), (5, 2)] {
+            let res: TxAddRes = client
+                .post(format!("{}/tx/add", node.url()))
+                .json(&transfer(&accounts[0], &accounts[1], fee, nonce))
+                .send()
+                .unwrap()
+                .json()
+                .unwrap();
+            hashes.push(res.hash);
+        }
+
+        let mempool: MempoolRes = client
+            .get(format!("{}/mempool?limit=1", node.url()))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(mempool.total_txs, 2);
+        assert_eq!(mempool.txs.len(), 1);
+        assert_eq!(mempool.txs[0].hash, hashes[1]);
+
+        let pending: MempoolTxRes = client
+            .get(format!("{}/mempool/{}", node.url(), hashes[0]))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        assert_eq!(pending.nonce, 1);
+
+        let drop_url = format!("{}/mempool/{}", node.url(), hashes[0]);
+        let res = client.delete(&drop_url).send().unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = client
+            .delete(&drop_url)
+            .bearer_auth("wrong")
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = client
+            .delete(&drop_url)
+            .bearer_auth("secret")
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = client
+            .get(format!("{}/mempool/{}", node.url(), hashes[0]))
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(node.state().mempool().is_empty());
+        node.stop().unwrap();
+
+        let node = spawn_interval_node(&accounts, None);
+        let res = client
+            .delete(format!("{}/mempool/{}", node.url(), hashes[1]))
+            .bearer_auth("secret")
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        node.stop().unwrap();
+    }
+
+    #[test]
+    fn block_endpoints_tell_pruned_from_missing_and_corrupt_blocks() {
+        let accounts = dev_accounts(2).unwrap();
+        let data_dir = std::env::temp_dir().join(format!("tbb-node-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let data_dir = data_dir.to_str().unwrap();
+        let spec = dev_chain_spec(&accounts, 0).unwrap();
+        init_data_dir(data_dir, &spec, StorageConfig::default()).unwrap();
+        let config = Config {
+            pruning: PruningConfig {
+                keep_blocks: 2,
+                interval: 1,
+            },
+            ..Config::default()
+        };
+        config
+            .write(&get_config_file_path(data_dir).unwrap())
+            .unwrap();
+        let node = NodeBuilder::data_dir(data_dir)
+            .miner(&accounts[0].account)
+            .seal(SealMode::Instant)
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .spawn()
+            .unwrap();
+        let client = http();
+        for nonce in 1..=4 {
+            let res = client
+                .post(format!("{}/tx/add", node.url()))
+                .json(&transfer(&accounts[0], &accounts[1], 1, nonce))
+                .send()
+                .unwrap();
+            assert!(res.status().is_success());
+        }
+        let get_block = |number: u64| {
+            client
+                .get(format!("{}/blocks/{}", node.url(), number))
+                .send()
+                .unwrap()
+        };
+
+        assert_eq!(get_block(3).status(), StatusCode::OK);
+        let res = get_block(0);
+        assert_eq!(res.status(), StatusCode::GONE);
+        let pruned: PrunedRes = res.json().unwrap();
+        assert_eq!(pruned.error.code, "pruned");
+        assert_eq!(pruned.header.unwrap().header.number(), 0);
+        let res = get_block(9);
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            res.json::<ErrorRes>().unwrap().error.code,
+            "block_not_found"
+        );
+
+        let mut block_db = std::fs::OpenOptions::new()
+            .append(true)
+            .open(get_blocks_db_file_path(data_dir).unwrap())
+            .unwrap();
+        writeln!(block_db, "not a block").unwrap();
+        let res = get_block(9);
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.json::<ErrorRes>().unwrap().error.code, "corrupt");
+        node.stop().unwrap();
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+}
@@ -1,3 +1,8 @@
+mod api;
+mod builder;
+#[allow(clippy::module_inception)]
 mod node;
 
+pub use api::*;
+pub use builder::*;
 pub use node::*;
@@ -1,58 +1,160 @@
-use std::collections::HashMap;
+use std::{net::SocketAddr, sync::Mutex, time::Duration};
 
-use crate::database::{new_account, State, Tx};
-use actix_web::{middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
+use super::api::{
+    AccountRes, BalanceRes, BlockHeaderRes, BlockRes, ErrorBody, ErrorRes, MempoolDropRes,
+    MempoolQuery, MempoolRes, MempoolTxRes, PrunedRes, StatusRes, TxAddReq, TxAddRes,
+};
+use crate::database::{
+    new_account, read_stored_block, DatabaseError, Hash, HeaderFS, State, StoredBlock, Tx,
+};
+use actix_web::{
+    dev::Server,
+    error::{InternalError, JsonPayloadError},
+    http::StatusCode,
+    middleware::Logger,
+    rt::time,
+    web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
 use data_encoding::HEXLOWER;
 
-const HTTP_PORT: u16 = 8080;
+pub(super) const HTTP_PORT: u16 = 8080;
+const MEMPOOL_PAGE_LIMIT: usize = 100;
 
-#[derive(Debug, serde::Serialize)]
-struct BalanceRes {
-    balances: HashMap<String, u64>,
-    #[serde(rename = "block_hash")]
-    hash: String,
+#[derive(Debug)]
+pub(super) struct Node {
+    pub(super) state: Mutex<State>,
+    pub(super) admin_token: Option<String>,
+    pub(super) instant_seal: bool,
+    pub(super) allow_unsigned: bool,
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct TxAddReq {
-    from: String,
-    to: String,
-    value: u64,
-    data: Option<String>,
+fn error_status(err: &DatabaseError) -> StatusCode {
+    match err {
+        DatabaseError::InsufficientBalance { .. }
+        | DatabaseError::UnknownAccount(_)
+        | DatabaseError::Overflow { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        DatabaseError::InvalidAmount { .. }
+        | DatabaseError::FeeTooLow { .. }
+        | DatabaseError::CoinbaseNotAllowed
+        | DatabaseError::WrongChain { .. }
+        | DatabaseError::DataTooLarge { .. }
+        | DatabaseError::BadSignature(_) => StatusCode::BAD_REQUEST,
+        DatabaseError::InvalidNonce { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        DatabaseError::DuplicateTx | DatabaseError::ReplacementUnderpriced { .. } => {
+            StatusCode::CONFLICT
+        }
+        DatabaseError::MempoolFull => StatusCode::SERVICE_UNAVAILABLE,
+        DatabaseError::BlockNotFound(_) => StatusCode::NOT_FOUND,
+        DatabaseError::InvalidBlockNumber { .. }
+        | DatabaseError::ParentMismatch { .. }
+        | DatabaseError::KeyMismatch { .. }
+        | DatabaseError::TimeBeforeParent { .. }
+        | DatabaseError::TooManyTxs { .. }
+        | DatabaseError::InvalidCoinbase(_)
+        | DatabaseError::UnauthorizedProducer(_)
+        | DatabaseError::BlockTooLarge { .. } => StatusCode::CONFLICT,
+        DatabaseError::Corrupt(_)
+        | DatabaseError::BadGenesis(_)
+        | DatabaseError::BadSnapshot(_) => StatusCode::SERVICE_UNAVAILABLE,
+        DatabaseError::SupplyMismatch { .. }
+        | DatabaseError::SupplyOverflow
+        | DatabaseError::AlreadyInitialized(_)
+        | DatabaseError::DataDirLocked { .. }
+        | DatabaseError::ReadOnly
+        | DatabaseError::Io(_)
+        | DatabaseError::Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
 }
 
-#[derive(Debug, serde::Serialize)]
-struct TxAddRes {
-    #[serde(rename = "block_hash")]
-    hash: String,
+fn error_response(err: &DatabaseError) -> HttpResponse {
+    error_body_response(error_status(err), err.code(), &err.to_string())
 }
 
-#[derive(Debug, serde::Serialize)]
-struct StatusRes {
-    #[serde(rename = "block_hash")]
-    hash: String,
-    #[serde(rename = "block_number")]
-    number: u64,
+fn error_body_response(status: StatusCode, code: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(ErrorRes {
+        error: ErrorBody {
+            code: code.to_string(),
+            message: message.to_string(),
+        },
+    })
+}
+
+fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = error_body_response(
+        StatusCode::BAD_REQUEST,
+        "malformed_request",
+        &err.to_string(),
+    );
+    InternalError::from_response(err, response).into()
+}
+
+fn parse_tx_hash(hash: &str) -> Result<Hash, HttpResponse> {
+    HEXLOWER
+        .decode(hash.to_lowercase().as_bytes())
+        .ok()
+        .and_then(|bytes| Hash::try_from(bytes.as_slice()).ok())
+        .ok_or_else(|| {
+            error_body_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_hash",
+                &format!("{} is not a 32 byte hex tx hash", hash),
+            )
+        })
+}
+
+fn check_admin(req: &HttpRequest, node: &Node) -> Result<(), HttpResponse> {
+    let token = match &node.admin_token {
+        Some(token) => token,
+        None => {
+            return Err(error_body_response(
+                StatusCode::FORBIDDEN,
+                "admin_disabled",
+                "admin endpoints are disabled, start the node with --admin-token",
+            ))
+        }
+    };
+    let provided = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let authorized = provided.is_some_and(|provided| {
+        ring::constant_time::verify_slices_are_equal(provided.as_bytes(), token.as_bytes()).is_ok()
+    });
+    if !authorized {
+        return Err(error_body_response(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "missing or invalid admin token",
+        ));
+    }
+    Ok(())
 }
 
 #[actix_web::get("/node/status")]
-async fn node_status(data_dir: web::Data<String>) -> impl Responder {
-    let state = State::new_state_from_disk(&data_dir);
-    state.close();
-    let block_number = match state.latest_block() {
-        Some(block) => block.header().number(),
+async fn node_status(node: web::Data<Node>) -> impl Responder {
+    let state = node.state.lock().unwrap();
+    let block_number = match state.latest_header() {
+        Some(header) => header.number(),
         None => 0,
     };
+    let next_block_number = state.next_block_number();
     let output = StatusRes {
+        chain_id: state.chain_id().to_string(),
+        genesis_hash: HEXLOWER.encode(&state.genesis_hash()),
         hash: HEXLOWER.encode(&state.latest_block_hash()),
         number: block_number,
+        min_fee: state.consensus().fees.min_tx_fee,
+        circulating_supply: state.circulating_supply(),
+        max_supply: state.consensus().rewards.max_supply,
+        block_reward: state.block_reward(next_block_number),
+        next_halving_height: state.next_halving_height(next_block_number),
     };
     HttpResponse::Ok().json(output)
 }
 #[actix_web::get("/balances/list")]
-async fn list_balances_handler(data_dir: web::Data<String>) -> impl Responder {
-    let state = State::new_state_from_disk(&data_dir);
-    state.close();
+async fn list_balances_handler(node: web::Data<Node>) -> impl Responder {
+    let state = node.state.lock().unwrap();
     let output = BalanceRes {
         balances: state.get_balances().clone(),
         hash: HEXLOWER.encode(&state.latest_block_hash()),
@@ -60,52 +162,263 @@ async fn list_balances_handler(data_dir: web::Data<String>) -> impl Responder {
     HttpResponse::Ok().json(output)
 }
 
+#[actix_web::get("/accounts/{account}")]
+async fn account_handler(path: web::Path<String>, node: web::Data<Node>) -> impl Responder {
+    let state = node.state.lock().unwrap();
+    let account = new_account(&path);
+    let output = AccountRes {
+        balance: state
+            .get_balances()
+            .get(&account)
+            .copied()
+            .unwrap_or_default(),
+        nonce: state.next_nonce(&account),
+        pending_nonce: state.next_pending_nonce(&account),
+        account,
+    };
+    HttpResponse::Ok().json(output)
+}
+
 #[actix_web::post("/tx/add")]
-async fn tx_add_handler(
-    payload: web::Json<TxAddReq>,
-    data_dir: web::Data<String>,
-) -> impl Responder {
-    let mut state = State::new_state_from_disk(&data_dir);
-    state.close();
+async fn tx_add_handler(payload: web::Json<TxAddReq>, node: web::Data<Node>) -> impl Responder {
+    let mut state = node.state.lock().unwrap();
     let from = new_account(&payload.from.clone());
     let to = new_account(&payload.to.clone());
     let value = payload.value;
+    let fee = payload.fee.unwrap_or(state.consensus().fees.min_tx_fee);
+    let nonce = payload
+        .nonce
+        .unwrap_or_else(|| state.next_pending_nonce(&from));
     let data = match &payload.data {
         Some(data) => data.clone(),
         None => "".to_string(),
     };
 
-    let tx = Tx::new(from, to, &value, &data);
-    let Ok(_) = state.add_tx(&tx) else {
-        println!("Handler: Error adding transaction");
-        return HttpResponse::InternalServerError().finish();
+    let chain_id = match &payload.chain_id {
+        Some(chain_id) => chain_id.clone(),
+        None => state.chain_id().to_string(),
+    };
+
+    let tx = Tx::new(&chain_id, from, to, value, fee, nonce, &data);
+    let tx = match &payload.signature {
+        Some(signature) => tx.with_signature(signature),
+        None if !node.allow_unsigned => return error_response(&DatabaseError::BadSignature(
+            "unsigned txs are not accepted, sign the tx or start the node with --allow-unsigned"
+                .to_string(),
+        )),
+        None => tx,
+    };
+    if let Err(err) = state.add_tx(&tx) {
+        return error_response(&err);
     };
-    let Ok(persist_tx) = state.persist() else {
-        println!("Handler: Error persisting transaction");
-        return HttpResponse::InternalServerError().finish();
+    let tx_hash = match tx.hash() {
+        Ok(hash) => hash,
+        Err(err) => return error_response(&err),
     };
+    if node.instant_seal {
+        seal_block(&mut state);
+    }
     let output = TxAddRes {
-        hash: HEXLOWER.encode(&persist_tx),
+        hash: HEXLOWER.encode(&tx_hash),
     };
     HttpResponse::Ok().json(output)
 }
 
-#[actix_web::main]
-pub async fn run(data_dir: &str) -> std::io::Result<()> {
-    println!("Listening on HTTP port: {}", HTTP_PORT);
-    std::env::set_var("RUST_LOG", "debug");
-    env_logger::init();
-    let data_dir = data_dir.to_string();
-    let _ = HttpServer::new(move || {
+#[actix_web::get("/mempool")]
+async fn mempool_list_handler(
+    query: web::Query<MempoolQuery>,
+    node: web::Data<Node>,
+) -> impl Responder {
+    let state = node.state.lock().unwrap();
+    let mempool = state.mempool();
+    let offset = query.offset.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(MEMPOOL_PAGE_LIMIT)
+        .min(MEMPOOL_PAGE_LIMIT);
+    let txs = mempool
+        .txs()
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(MempoolTxRes::from)
+        .collect();
+    let output = MempoolRes {
+        total_txs: mempool.len(),
+        total_bytes: mempool.bytes(),
+        offset,
+        limit,
+        txs,
+        fee_histogram: mempool.fee_histogram(),
+    };
+    HttpResponse::Ok().json(output)
+}
+
+#[actix_web::get("/mempool/{tx_hash}")]
+async fn mempool_get_handler(path: web::Path<String>, node: web::Data<Node>) -> impl Responder {
+    let hash = match parse_tx_hash(&path) {
+        Ok(hash) => hash,
+        Err(response) => return response,
+    };
+    let state = node.state.lock().unwrap();
+    match state.mempool().get_by_hash(&hash) {
+        Some(pending) => HttpResponse::Ok().json(MempoolTxRes::from(pending)),
+        None => error_body_response(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            &format!("tx {} is not in the mempool", path),
+        ),
+    }
+}
+
+#[actix_web::delete("/mempool/{tx_hash}")]
+async fn mempool_drop_handler(
+    req: HttpRequest,
+    path: web::Path<String>,
+    node: web::Data<Node>,
+) -> impl Responder {
+    if let Err(response) = check_admin(&req, &node) {
+        return response;
+    }
+    let hash = match parse_tx_hash(&path) {
+        Ok(hash) => hash,
+        Err(response) => return response,
+    };
+    let mut state = node.state.lock().unwrap();
+    match state.remove_pending_tx(&hash) {
+        Some(_) => HttpResponse::Ok().json(MempoolDropRes {
+            dropped: true,
+            hash: HEXLOWER.encode(&hash),
+        }),
+        None => error_body_response(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            &format!("tx {} is not in the mempool", path),
+        ),
+    }
+}
+
+pub(super) async fn produce_blocks(node: web::Data<Node>) {
+    let block_interval_secs = node
+        .state
+        .lock()
+        .unwrap()
+        .consensus()
+        .engine
+        .block_interval_secs;
+    let period = Duration::from_secs(block_interval_secs);
+    // The first tick of a plain interval fires at once; wait a full period instead.
+    let mut interval = time::interval_at(time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        let mut state = node.state.lock().unwrap();
+        if state.mempool().is_empty() {
+            continue;
+        }
+        seal_block(&mut state);
+    }
+}
+
+fn seal_block(state: &mut State) {
+    println!(
+        "Node: Producing block from {} pending txs ({} bytes)",
+        state.mempool().len(),
+        state.mempool().bytes()
+    );
+    if let Err(err) = state.persist().and_then(|_| state.close()) {
+        println!("Node: Error producing block: {}", err);
+    }
+}
+
+fn stored_block(node: &Node, number: u64) -> Result<(StoredBlock, u64), HttpResponse> {
+    let ((blocks, store), keep_blocks) = {
+        let state = node.state.lock().unwrap();
+        (state.stores(), state.pruning().keep_blocks)
+    };
+    let stored = read_stored_block(&*blocks.lock().unwrap(), &*store.lock().unwrap(), number);
+    stored
+        .map(|stored| (stored, keep_blocks))
+        .map_err(|err| error_response(&err))
+}
+
+fn pruned_response(number: u64, keep_blocks: u64, header: Option<HeaderFS>) -> HttpResponse {
+    let message = if keep_blocks > 0 {
+        format!(
+            "block {} was pruned, this node keeps bodies of the last {} blocks",
+            number, keep_blocks
+        )
+    } else {
+        format!(
+            "block {} precedes the snapshot this node was started from",
+            number
+        )
+    };
+    HttpResponse::Gone().json(PrunedRes {
+        error: ErrorBody {
+            code: "pruned".to_string(),
+            message,
+        },
+        header: header.map(BlockHeaderRes::from),
+    })
+}
+
+#[actix_web::get("/blocks/{number}")]
+async fn block_get_handler(path: web::Path<u64>, node: web::Data<Node>) -> impl Responder {
+    let number = path.into_inner();
+    match stored_block(&node, number) {
+        Ok((StoredBlock::Full(block_fs), _)) => HttpResponse::Ok().json(BlockRes {
+            block_hash: HEXLOWER.encode(&block_fs.key),
+            block: block_fs.value,
+        }),
+        Ok((StoredBlock::Pruned { number, header }, keep_blocks)) => {
+            pruned_response(number, keep_blocks, header)
+        }
+        Err(response) => response,
+    }
+}
+
+#[actix_web::get("/blocks/{number}/header")]
+async fn block_header_handler(path: web::Path<u64>, node: web::Data<Node>) -> impl Responder {
+    let number = path.into_inner();
+    match stored_block(&node, number) {
+        Ok((StoredBlock::Full(block_fs), _)) => HttpResponse::Ok().json(BlockHeaderRes {
+            block_hash: HEXLOWER.encode(&block_fs.key),
+            header: block_fs.value.header().clone(),
+        }),
+        Ok((
+            StoredBlock::Pruned {
+                header: Some(header),
+                ..
+            },
+            _,
+        )) => HttpResponse::Ok().json(BlockHeaderRes::from(header)),
+        Ok((StoredBlock::Pruned { number, .. }, keep_blocks)) => {
+            pruned_response(number, keep_blocks, None)
+        }
+        Err(response) => response,
+    }
+}
+
+pub(super) fn new_server(
+    node: web::Data<Node>,
+    addr: SocketAddr,
+) -> std::io::Result<(Server, SocketAddr)> {
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .service(list_balances_handler)
             .service(node_status)
+            .service(account_handler)
             .service(tx_add_handler)
-            .app_data(web::Data::new(data_dir.clone()))
+            .service(mempool_list_handler)
+            .service(mempool_get_handler)
+            .service(mempool_drop_handler)
+            .service(block_get_handler)
+            .service(block_header_handler)
+            .app_data(node.clone())
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
     })
-    .bind(("127.0.0.1", HTTP_PORT))?
-    .run()
-    .await;
-    Ok(())
+    .bind(addr)?;
+    let addr = server.addrs()[0];
+    Ok((server.run(), addr))
 }